@@ -1,16 +1,40 @@
 #version 330 core
 
 uniform sampler2D TexFace;
+uniform float TileSize;
+// brightness of sky light at the current time of day
+uniform float SkyLight;
+uniform vec3 CameraPos;
+uniform vec3 FogColor;
+// distances from the camera at which fog starts and becomes opaque
+uniform vec2 FogRange;
 
 in VS_OUTPUT {
     vec3 Position;
     vec2 Uv;
-    float LightBrightness;
+    vec2 Tile;
+    float SkyLight;
+    vec3 BlockLight;
+    float Occlusion;
 } IN;
 
 out vec4 Color;
 
 void main()
 {
-    Color = texture(TexFace, IN.Uv) * IN.LightBrightness;
+    // Uv is measured in tiles, so repeat the tile across merged faces
+    vec2 atlasUv = IN.Tile + fract(IN.Uv) * TileSize;
+
+    vec4 texel = texture(TexFace, atlasUv);
+    if (texel.a < 0.5) {
+        // cut out the transparent parts of blocks such as glass
+        discard;
+    }
+
+    vec3 lightLevel = max(vec3(IN.SkyLight * SkyLight), IN.BlockLight);
+    vec3 lightColor = ((1.0f / 16.0f) + (lightLevel / 16.0f)) * IN.Occlusion;
+
+    float fog = smoothstep(FogRange.x, FogRange.y, distance(IN.Position, CameraPos));
+
+    Color = vec4(mix(texel.rgb * lightColor, FogColor, fog), texel.a);
 }
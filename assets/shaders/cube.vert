@@ -2,7 +2,9 @@
 
 layout (location = 0) in vec3 Position;
 layout (location = 1) in vec2 Uv;
-layout (location = 2) in float LightLevel;
+layout (location = 2) in uint Light;
+layout (location = 3) in vec2 Tile;
+layout (location = 4) in float AmbientOcclusion;
 
 uniform mat4 View;
 uniform mat4 Projection;
@@ -10,7 +12,10 @@ uniform mat4 Projection;
 out VS_OUTPUT {
     vec3 Position;
     vec2 Uv;
-    float LightBrightness;
+    vec2 Tile;
+    float SkyLight;
+    vec3 BlockLight;
+    float Occlusion;
 } OUT;
 
 void main()
@@ -19,5 +24,16 @@ void main()
 
     OUT.Position = Position;
     OUT.Uv = Uv;
-    OUT.LightBrightness = (1.0f / 16.0f) + (LightLevel / 16.0f);
+    OUT.Tile = Tile;
+
+    // unpack the sky light and the red, green and blue block light
+    OUT.SkyLight = float(Light & 0xFu);
+    OUT.BlockLight = vec3(
+        float((Light >> 4) & 0xFu),
+        float((Light >> 8) & 0xFu),
+        float((Light >> 12) & 0xFu)
+    );
+
+    // corners enclosed by blocks are darkened, down to 40% when fully enclosed
+    OUT.Occlusion = mix(0.4f, 1.0f, AmbientOcclusion / 3.0f);
 }
@@ -0,0 +1,14 @@
+#version 330 core
+
+layout (location = 0) in vec3 Position;
+
+uniform mat4 View;
+uniform mat4 Projection;
+// corners of the box being outlined
+uniform vec3 Min;
+uniform vec3 Max;
+
+void main()
+{
+    gl_Position = Projection * View * vec4(mix(Min, Max, Position), 1.0);
+}
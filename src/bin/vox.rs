@@ -0,0 +1,131 @@
+//! Import MagicaVoxel models into a saved world, or export part of it as a
+//! model, without running the game.
+//!
+//! ```text
+//! vox import <file.vox> <x> <y> <z> [--model <index>] [--world <directory>]
+//! vox export <file.vox> <x1> <y1> <z1> <x2> <y2> <z2> [--world <directory>]
+//! ```
+
+extern crate failure;
+extern crate game;
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
+
+use game::{debug, SAVE_DIRECTORY, WORLD_SEED};
+use game::resources::Resources;
+use game::vox::{self, VoxFile};
+use game::world::BlockPos;
+use game::world::block::BlockRegistry;
+use game::world::generator::HeightmapGenerator;
+use game::world::storage::{OfflineWorld, WorldStorage};
+
+const USAGE: &str = "\
+usage: vox import <file.vox> <x> <y> <z> [--model <index>] [--world <directory>]
+       vox export <file.vox> <x1> <y1> <z1> <x2> <y2> <z2> [--world <directory>]";
+
+fn main() {
+    let args = match Args::parse(env::args().skip(1)) {
+        Some(args) => args,
+        None => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Err(e) = run(args) {
+        eprintln!("{}", debug::failure_to_string(e));
+        process::exit(1);
+    }
+}
+
+enum Command {
+    Import { origin: BlockPos, model: usize },
+    Export { min: BlockPos, max: BlockPos },
+}
+
+struct Args {
+    command: Command,
+    file: PathBuf,
+    world: PathBuf,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Option<Args> {
+        let mut positional = Vec::new();
+        let mut model = 0;
+        let mut world = PathBuf::from(SAVE_DIRECTORY);
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--model" => model = args.next()?.parse().ok()?,
+                "--world" => world = args.next()?.into(),
+                _ => positional.push(arg),
+            }
+        }
+
+        let (command, file, coordinates) = match positional.split_first()? {
+            (command, rest) if !rest.is_empty() => (command.as_str(), &rest[0], &rest[1..]),
+            _ => return None,
+        };
+        let coordinates = coordinates.iter()
+            .map(|coordinate| coordinate.parse().ok())
+            .collect::<Option<Vec<i64>>>()?;
+
+        let command = match (command, coordinates.as_slice()) {
+            ("import", &[x, y, z]) => Command::Import { origin: BlockPos::new(x, y, z), model },
+            ("export", &[x1, y1, z1, x2, y2, z2]) => Command::Export {
+                min: BlockPos::new(x1, y1, z1),
+                max: BlockPos::new(x2, y2, z2),
+            },
+            _ => return None,
+        };
+
+        Some(Args {
+            command,
+            file: file.into(),
+            world,
+        })
+    }
+}
+
+fn run(args: Args) -> Result<(), failure::Error> {
+    let res = Resources::from_relative_exe_path(Path::new("assets"))?;
+    let registry = Arc::new(BlockRegistry::from_res(&res, "blocks.ron")?);
+    let generator = HeightmapGenerator::new(WORLD_SEED, &registry)?;
+    let storage = WorldStorage::open(&args.world, Arc::clone(&registry))?;
+    let mut world = OfflineWorld::new(storage, Box::new(generator));
+
+    match args.command {
+        Command::Import { origin, model } => {
+            let file = VoxFile::load(&args.file)?;
+            let size = file.models.get(model)
+                .ok_or(vox::Error::MissingModel { index: model })?
+                .size;
+            let max = BlockPos::new(
+                origin.x + size[0] as i64 - 1,
+                origin.y + size[1] as i64 - 1,
+                origin.z + size[2] as i64 - 1,
+            );
+
+            world.load_area(origin, max)?;
+            let placed = vox::stamp_model(&mut world, &registry, &file, model, origin)?;
+            world.save()?;
+
+            println!("Placed {} blocks", placed);
+        }
+        Command::Export { min, max } => {
+            // refuse regions too large to export before loading them
+            let (min, max) = vox::export_bounds(min, max)?;
+            world.load_area(min, max)?;
+            let file = vox::export_region(&world, &registry, min, max)?;
+            file.save(&args.file)?;
+
+            println!("Exported {} blocks", file.models[0].voxels.len());
+        }
+    }
+
+    Ok(())
+}
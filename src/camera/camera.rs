@@ -0,0 +1,30 @@
+use nalgebra as na;
+
+use super::NoClipMovement;
+
+/// A view into the world which can be rendered from, following the player.
+pub trait Camera {
+    fn get_view_matrix(&self) -> na::Matrix4<f32>;
+
+    fn get_projection_matrix(&self) -> na::Matrix4<f32>;
+
+    /// Position the camera is viewing from.
+    fn position(&self) -> na::Point3<f32>;
+
+    /// Update the projection for the aspect ratio of the window.
+    fn set_aspect(&mut self, aspect: f32);
+
+    /// Set the vertical field of view, in degrees.
+    fn set_fov(&mut self, fov: f32);
+
+    /// Move the camera to follow a point, such as the player's eyes.
+    fn set_target(&mut self, target: &na::Point3<f32>);
+
+    /// Turn the camera by a relative mouse movement.
+    fn rotate(&mut self, rel: &na::Vector2<f32>);
+
+    /// Get the direction of the movement state in world space, turned to
+    /// face the way the camera is looking. X and Y are horizontal, and Z is
+    /// the vertical movement.
+    fn movement_direction(&self, movement: &NoClipMovement) -> Option<na::Vector3<f32>>;
+}
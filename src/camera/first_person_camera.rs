@@ -0,0 +1,80 @@
+use nalgebra as na;
+
+use super::{Camera, MAX_PITCH, NoClipMovement};
+
+pub struct FirstPersonCamera {
+    position: na::Point3<f32>,
+    /// Rotation around the Z axis. Zero faces along Y+.
+    yaw: f32,
+    /// Rotation above the horizon. Positive looks upwards.
+    pitch: f32,
+    projection: na::Perspective3<f32>,
+}
+
+impl FirstPersonCamera {
+    /// Create a camera with a vertical field of view in degrees.
+    pub fn new(aspect: f32, fov: f32, znear: f32, zfar: f32) -> FirstPersonCamera {
+        FirstPersonCamera {
+            position: na::Point3::origin(),
+            yaw: 0.0,
+            pitch: 0.0,
+            projection: na::Perspective3::new(aspect, fov.to_radians(), znear, zfar),
+        }
+    }
+
+    /// Rotation from camera space, looking along Z-, to world space.
+    fn rotation(&self) -> na::UnitQuaternion<f32> {
+        na::UnitQuaternion::from_axis_angle(&na::Vector3::z_axis(), self.yaw)
+            * na::UnitQuaternion::from_axis_angle(
+                &na::Vector3::x_axis(),
+                ::std::f32::consts::FRAC_PI_2 + self.pitch,
+            )
+    }
+}
+
+impl Camera for FirstPersonCamera {
+    fn get_view_matrix(&self) -> na::Matrix4<f32> {
+        (na::Translation3::<f32>::from(self.position.coords) * self.rotation())
+            .inverse()
+            .to_homogeneous()
+    }
+
+    fn get_projection_matrix(&self) -> na::Matrix4<f32> {
+        self.projection.into_inner()
+    }
+
+    fn position(&self) -> na::Point3<f32> {
+        self.position
+    }
+
+    fn set_aspect(&mut self, aspect: f32) {
+        self.projection.set_aspect(aspect);
+    }
+
+    fn set_fov(&mut self, fov: f32) {
+        self.projection.set_fovy(fov.to_radians());
+    }
+
+    fn set_target(&mut self, target: &na::Point3<f32>) {
+        self.position = *target;
+    }
+
+    fn rotate(&mut self, rel: &na::Vector2<f32>) {
+        self.yaw -= rel.x * 0.005;
+        self.pitch = (self.pitch + rel.y * 0.005).clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    fn movement_direction(&self, movement: &NoClipMovement) -> Option<na::Vector3<f32>> {
+        if !movement.has_movement() {
+            return None;
+        }
+
+        let mov3 = movement.get_vector();
+        // move along the ground regardless of pitch
+        let right = na::Vector2::new(self.yaw.cos(), self.yaw.sin());
+        let forward = na::Vector2::new(-self.yaw.sin(), self.yaw.cos());
+        let xy = right * mov3.x + forward * mov3.y;
+
+        na::Vector3::new(xy.x, xy.y, mov3.z).try_normalize(0.01)
+    }
+}
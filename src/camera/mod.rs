@@ -1,11 +1,20 @@
-pub use basic::Camera;
+pub use camera::Camera;
+pub use first_person_camera::FirstPersonCamera;
 pub use movement::NoClipMovement;
 pub use target_camera::TargetCamera;
 
-mod basic;
+mod camera;
+mod first_person_camera;
 mod target_camera;
 mod movement;
 
+/// Vertical field of view in degrees.
 pub const FIELD_OF_VIEW: f32 = 60.0;
+/// Vertical field of view in degrees while zoomed in.
+pub const ZOOM_FIELD_OF_VIEW: f32 = 20.0;
 pub const Z_NEAR: f32 = 0.01;
 pub const Z_FAR: f32 = 1000.0;
+
+/// Furthest the cameras can look up or down, just short of straight up so the
+/// view does not flip over.
+const MAX_PITCH: f32 = ::std::f32::consts::FRAC_PI_2 - 0.01;
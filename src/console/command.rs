@@ -0,0 +1,179 @@
+use crate::schematic::{Mirror, Rotation, Transform};
+use crate::world::BlockPos;
+
+pub const USAGE: &str = "\
+commands:
+  help
+  schem save <name> <x1> <y1> <z1> <x2> <y2> <z2>
+  schem load <name> <x> <y> <z> [rotate <90|180|270>] [mirror <x|y>]";
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Unknown command {}", name)]
+    UnknownCommand { name: String },
+    #[fail(display = "Wrong arguments for {}", name)]
+    WrongArguments { name: String },
+    #[fail(display = "Invalid argument {}", argument)]
+    InvalidArgument { argument: String },
+}
+
+/// A command typed into the console.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Help,
+    /// Copy the blocks between two corners into a schematic file.
+    SchematicSave { name: String, min: BlockPos, max: BlockPos },
+    /// Paste a schematic file with its lowest corner at `origin`.
+    SchematicLoad { name: String, origin: BlockPos, transform: Transform },
+}
+
+impl Command {
+    /// Parse a line of input. A leading `/` is optional.
+    pub fn parse(line: &str) -> Result<Command, Error> {
+        let words: Vec<&str> = line.trim().trim_start_matches('/').split_whitespace().collect();
+
+        match words.as_slice() {
+            ["help"] => Ok(Command::Help),
+            ["schem", "save", name, corners @ ..] => match corners {
+                [x1, y1, z1, x2, y2, z2] => Ok(Command::SchematicSave {
+                    name: parse_name(name)?,
+                    min: parse_position(x1, y1, z1)?,
+                    max: parse_position(x2, y2, z2)?,
+                }),
+                _ => Err(Error::WrongArguments { name: "schem save".into() }),
+            },
+            ["schem", "load", name, x, y, z, options @ ..] => {
+                let mut transform = Transform::IDENTITY;
+
+                for option in options.chunks(2) {
+                    transform = match option {
+                        ["rotate", degrees] => Transform {
+                            rotation: degrees.parse().ok()
+                                .and_then(Rotation::from_degrees)
+                                .ok_or_else(|| invalid_argument(degrees))?,
+                            ..transform
+                        },
+                        ["mirror", axis] => Transform {
+                            mirror: match *axis {
+                                "x" => Mirror::X,
+                                "y" => Mirror::Y,
+                                _ => return Err(invalid_argument(axis)),
+                            },
+                            ..transform
+                        },
+                        _ => return Err(Error::WrongArguments { name: "schem load".into() }),
+                    };
+                }
+
+                Ok(Command::SchematicLoad {
+                    name: parse_name(name)?,
+                    origin: parse_position(x, y, z)?,
+                    transform,
+                })
+            }
+            ["schem", ..] => Err(Error::WrongArguments { name: "schem".into() }),
+            [name, ..] => Err(Error::UnknownCommand { name: name.to_string() }),
+            [] => Err(Error::UnknownCommand { name: String::new() }),
+        }
+    }
+}
+
+fn invalid_argument(argument: &str) -> Error {
+    Error::InvalidArgument { argument: argument.into() }
+}
+
+/// Schematic names become file names, so may not contain a path.
+fn parse_name(name: &str) -> Result<String, Error> {
+    let valid = name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-');
+
+    if valid {
+        Ok(name.into())
+    } else {
+        Err(invalid_argument(name))
+    }
+}
+
+fn parse_position(x: &str, y: &str, z: &str) -> Result<BlockPos, Error> {
+    let coordinate = |value: &str| value.parse().map_err(|_| invalid_argument(value));
+
+    Ok(BlockPos::new(coordinate(x)?, coordinate(y)?, coordinate(z)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_commands_are_parsed() {
+        assert_eq!(Command::parse("help").unwrap(), Command::Help);
+        assert_eq!(Command::parse("  /help ").unwrap(), Command::Help);
+
+        assert_eq!(Command::parse("schem save house 1 2 3 -4 -5 -6").unwrap(), Command::SchematicSave {
+            name: "house".into(),
+            min: BlockPos::new(1, 2, 3),
+            max: BlockPos::new(-4, -5, -6),
+        });
+
+        assert_eq!(Command::parse("/schem load big_house-2 10 0 -3").unwrap(), Command::SchematicLoad {
+            name: "big_house-2".into(),
+            origin: BlockPos::new(10, 0, -3),
+            transform: Transform::IDENTITY,
+        });
+
+        assert_eq!(Command::parse("schem load house 0 0 0 mirror y rotate 270").unwrap(), Command::SchematicLoad {
+            name: "house".into(),
+            origin: BlockPos::new(0, 0, 0),
+            transform: Transform { rotation: Rotation::Clockwise270, mirror: Mirror::Y },
+        });
+    }
+
+    #[test]
+    fn unknown_commands_are_rejected() {
+        for line in &["", "/", "jump", "Help"] {
+            match Command::parse(line) {
+                Err(Error::UnknownCommand { .. }) => {}
+                result => panic!("{:?}: expected an unknown command error, got {:?}", line, result),
+            }
+        }
+    }
+
+    #[test]
+    fn wrong_arguments_are_rejected() {
+        let lines = [
+            "schem",
+            "schem copy house",
+            "schem save house 1 2 3",
+            "schem save house 1 2 3 4 5 6 7",
+            "schem load house 1 2",
+            "schem load house 1 2 3 rotate",
+            "schem load house 1 2 3 flip x",
+        ];
+
+        for line in &lines {
+            match Command::parse(line) {
+                Err(Error::WrongArguments { .. }) => {}
+                result => panic!("{:?}: expected a wrong arguments error, got {:?}", line, result),
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_arguments_are_rejected() {
+        let lines = [
+            ("schem save house 1 2 x 4 5 6", "x"),
+            ("schem save house 1.5 2 3 4 5 6", "1.5"),
+            ("schem load house 1 2 3 rotate 45", "45"),
+            ("schem load house 1 2 3 mirror z", "z"),
+            ("schem save ../house 1 2 3 4 5 6", "../house"),
+            ("schem load dir/house 1 2 3", "dir/house"),
+            ("schem load dir\\house 1 2 3", "dir\\house"),
+        ];
+
+        for &(line, invalid) in &lines {
+            match Command::parse(line) {
+                Err(Error::InvalidArgument { argument }) => assert_eq!(argument, invalid, "{:?}", line),
+                result => panic!("{:?}: expected an invalid argument error, got {:?}", line, result),
+            }
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::{self, Path, PathBuf};
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+use crate::debug;
+use crate::schematic::{BlockMapping, Schematic};
+use crate::world::World;
+
+use super::command::{Command, Error, USAGE};
+
+/// Commands typed into standard input, or into the game and passed to
+/// `submit`, while the game is running.
+///
+/// Lines are read on a separate thread so that waiting for input never
+/// blocks a frame, and are run on the next `update`. Output is printed, and
+/// the latest message is kept for the game to show.
+pub struct Console {
+    lines: Receiver<String>,
+    sender: Sender<String>,
+    message: Option<String>,
+    mapping: BlockMapping,
+    schematic_directory: PathBuf,
+}
+
+impl Console {
+    /// Start reading commands. Schematics are saved to and loaded from
+    /// `schematic_directory`.
+    pub fn new(mapping: BlockMapping, schematic_directory: &Path) -> Console {
+        let (sender, receiver) = mpsc::channel();
+
+        // the thread is left blocked on stdin when the game exits
+        let stdin_sender = sender.clone();
+        thread::Builder::new()
+            .name("console".into())
+            .spawn(move || {
+                for line in io::stdin().lock().lines() {
+                    match line {
+                        Ok(line) => if stdin_sender.send(line).is_err() {
+                            break;
+                        },
+                        Err(_) => break,
+                    }
+                }
+            })
+            .expect("failed to spawn console thread");
+
+        Console {
+            lines: receiver,
+            sender,
+            message: None,
+            mapping,
+            schematic_directory: schematic_directory.into(),
+        }
+    }
+
+    /// Run a command typed into the game on the next update.
+    pub fn submit(&self, line: &str) {
+        // the receiver is owned by the console, so this can not fail
+        let _ = self.sender.send(line.into());
+    }
+
+    /// Print a message and keep it as the latest message.
+    pub fn print(&mut self, message: String) {
+        println!("{}", message);
+        self.message = Some(message);
+    }
+
+    /// The latest command output or message, if there has been one.
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+
+    /// Run every command typed since the last update.
+    pub fn update(&mut self, world: &mut World) {
+        while let Ok(line) = self.lines.try_recv() {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let result = Command::parse(&line)
+                .map_err(failure::Error::from)
+                .and_then(|command| self.execute(world, command));
+
+            match result {
+                Ok(output) => self.print(output),
+                Err(e) => self.print(debug::failure_to_string(e)),
+            }
+        }
+    }
+
+    fn execute(&mut self, world: &mut World, command: Command) -> Result<String, failure::Error> {
+        match command {
+            Command::Help => Ok(USAGE.into()),
+            Command::SchematicSave { name, min, max } => {
+                let schematic = Schematic::copy(world, min, max)?;
+
+                fs::create_dir_all(&self.schematic_directory)?;
+                schematic.save(&self.schematic_path(&name)?, world.registry(), &self.mapping)?;
+
+                let size = schematic.size();
+                Ok(format!("Saved {} ({} x {} x {})", name, size[0], size[1], size[2]))
+            }
+            Command::SchematicLoad { name, origin, transform } => {
+                let schematic = Schematic::load(&self.schematic_path(&name)?, world.registry(), &self.mapping)?;
+                if !schematic.unmapped.is_empty() {
+                    self.print(format!("Unknown blocks replaced with air: {}", schematic.unmapped.join(", ")));
+                }
+
+                let registry = Arc::clone(world.registry());
+                let placed = schematic.paste(world, &registry, origin, transform);
+                Ok(format!("Pasted {} blocks from {}", placed, name))
+            }
+        }
+    }
+
+    /// Path of the schematic file with the given name, which may not leave
+    /// the schematic directory.
+    fn schematic_path(&self, name: &str) -> Result<PathBuf, Error> {
+        let is_path = name.contains("..") || name.chars().any(|c| c == '\\' || path::is_separator(c));
+        if name.is_empty() || is_path {
+            return Err(Error::InvalidArgument { argument: name.into() });
+        }
+
+        Ok(self.schematic_directory.join(format!("{}.schem", name)))
+    }
+}
@@ -0,0 +1,5 @@
+pub use command::{Command, Error};
+pub use console::Console;
+
+mod command;
+mod console;
@@ -0,0 +1,29 @@
+extern crate crc32fast;
+#[macro_use]
+extern crate failure;
+extern crate flate2;
+extern crate gl;
+extern crate nalgebra;
+extern crate noise;
+#[macro_use]
+extern crate render_gl_derive;
+extern crate ron;
+extern crate serde;
+extern crate vec_2_10_10_10;
+
+pub mod camera;
+pub mod console;
+pub mod debug;
+pub mod player;
+pub mod render_gl;
+pub mod resources;
+pub mod schematic;
+pub mod vox;
+pub mod world;
+
+/// Seed used to generate the terrain.
+pub const WORLD_SEED: u32 = 1;
+/// Directory the world is saved in, relative to the working directory.
+pub const SAVE_DIRECTORY: &str = "saves/world";
+/// Directory schematics are saved in, relative to the working directory.
+pub const SCHEMATIC_DIRECTORY: &str = "schematics";
@@ -1,12 +1,8 @@
-#[macro_use]
-extern crate failure;
 extern crate floating_duration;
+extern crate game;
 extern crate gl;
 extern crate nalgebra;
-#[macro_use]
-extern crate render_gl_derive;
 extern crate sdl2;
-extern crate vec_2_10_10_10;
 
 use std::path::Path;
 use std::time::Instant;
@@ -15,17 +11,25 @@ use floating_duration::TimeAsFloat;
 use nalgebra as na;
 use sdl2::event::{Event, WindowEvent};
 use sdl2::keyboard::Scancode;
+use sdl2::mouse::MouseButton;
 
-use crate::camera::TargetCamera;
-use crate::render_gl::{ColorBuffer, data, Viewport};
-use crate::resources::Resources;
-use crate::world::World;
+use game::{debug, SAVE_DIRECTORY, SCHEMATIC_DIRECTORY, WORLD_SEED};
+use game::camera::{
+    Camera, FirstPersonCamera, NoClipMovement, TargetCamera, FIELD_OF_VIEW, ZOOM_FIELD_OF_VIEW,
+    Z_FAR, Z_NEAR,
+};
+use game::console::Console;
+use game::player::{Aabb, MovementMode, Player, PlayerInput};
+use game::render_gl::{ColorBuffer, Viewport};
+use game::resources::Resources;
+use game::schematic::BlockMapping;
+use game::world::{BlockOutline, MeshingMode, Ray, RaycastHit, World};
+use game::world::block::{AIR, Block};
 
-mod debug;
-pub mod camera;
-pub mod render_gl;
-pub mod resources;
-pub mod world;
+/// Fraction of a day the clock is moved by the `[` and `]` keys: one hour.
+const TIME_STEP: f32 = 1.0 / 24.0;
+/// Furthest distance from the camera at which blocks can be broken or placed.
+const REACH: f32 = 32.0;
 
 fn main() {
     if let Err(e) = run() {
@@ -45,7 +49,7 @@ fn run() -> Result<(), failure::Error> {
 
     let initial_window_size: (i32, i32) = (900, 700);
 
-    let window = video_subsystem
+    let mut window = video_subsystem
         .window(
             "Game",
             initial_window_size.0 as u32,
@@ -61,19 +65,44 @@ fn run() -> Result<(), failure::Error> {
     });
 
     let mut viewport = Viewport::for_window(900, 700);
-    let color_buffer = ColorBuffer::from_color(na::Vector3::new(0.3, 0.3, 0.5));
+    let mut world = World::new(&res, &gl, WORLD_SEED, Path::new(SAVE_DIRECTORY))?;
+    let mut color_buffer = ColorBuffer::from_color(world.clock.sky_color());
 
-    let mut world = World::new(&res, &gl)?;
+    let mapping = BlockMapping::from_res(&res, "schematic_blocks.ron", world.registry())?;
+    let mut console = Console::new(mapping, Path::new(SCHEMATIC_DIRECTORY));
 
-    let mut camera = TargetCamera::new(
+    let outline = BlockOutline::new(&res, &gl)?;
+    // every block but air can be placed, selected with the number keys;
+    // nothing is placed if only air is registered
+    let placeable: Vec<Block> = world.registry().iter()
+        .map(|(block, _)| block)
+        .filter(|&block| block != AIR)
+        .collect();
+    let mut selected = placeable.first().copied();
+    // the block under the cursor, and the ray which hit it
+    let mut target: Option<(Ray, RaycastHit)> = None;
+    let mut mouse_position = na::Point2::new(0.0, 0.0);
+    // dragging with the right button rotates the camera instead of placing
+    let mut right_dragged = false;
+    // the command being typed into the game while the command line is open,
+    // shown in the window title as there is no text rendering
+    let mut command_line: Option<String> = None;
+    let mut title = String::new();
+    let text_input = video_subsystem.text_input();
+    text_input.stop();
+
+    // start flying, as the terrain beneath has not been generated yet
+    let mut player = Player::new(na::Point3::new(8.0, 8.0, 32.0), MovementMode::NoClip);
+    let mut movement = NoClipMovement::new();
+
+    let mut first_person = false;
+    // vertical field of view in degrees, narrowed while zooming
+    let mut field_of_view = FIELD_OF_VIEW;
+    let mut camera = create_camera(
+        first_person,
         initial_window_size.0 as f32 / initial_window_size.1 as f32,
-        3.14 / 2.0,
-        0.01,
-        1000.0,
-        3.14 / 4.0,
-        0.0,
     );
-    camera.target = na::Vector3::new(8.0, 8.0, 17.5).into();
+    camera.set_target(&player.eye_position());
 
     viewport.set_used(&gl);
     color_buffer.set_used(&gl);
@@ -83,6 +112,39 @@ fn run() -> Result<(), failure::Error> {
     let mut event_pump = sdl.event_pump().unwrap();
     'main: loop {
         for event in event_pump.poll_iter() {
+            if let Some(line) = &mut command_line {
+                match event {
+                    Event::Quit { .. } => break 'main,
+                    Event::TextInput { text, .. } => line.push_str(&text),
+                    Event::KeyDown {
+                        scancode: Some(Scancode::Backspace),
+                        ..
+                    } => {
+                        line.pop();
+                    }
+                    Event::KeyDown {
+                        scancode: Some(Scancode::Return),
+                        ..
+                    } => {
+                        console.submit(line);
+                        command_line = None;
+                        text_input.stop();
+                    }
+                    Event::KeyDown {
+                        scancode: Some(Scancode::Escape),
+                        ..
+                    } => {
+                        command_line = None;
+                        text_input.stop();
+                    }
+                    // let go of keys which were held when the command line
+                    // was opened
+                    e @ Event::KeyUp { .. } => handle_camera_event(camera.as_mut(), &mut movement, &e),
+                    _ => (),
+                }
+                continue;
+            }
+
             match event {
                 Event::Quit { .. } => break 'main,
                 Event::Window {
@@ -91,14 +153,178 @@ fn run() -> Result<(), failure::Error> {
                 } => {
                     viewport.update_size(w, h);
                     viewport.set_used(&gl);
+                    camera.set_aspect(w as f32 / h.max(1) as f32);
+                }
+                Event::KeyDown {
+                    scancode: Some(Scancode::G),
+                    repeat: false,
+                    ..
+                } => {
+                    let mut options = world.mesh_options();
+                    options.mode = match options.mode {
+                        MeshingMode::Naive => MeshingMode::Greedy,
+                        MeshingMode::Greedy => MeshingMode::Naive,
+                    };
+                    world.set_mesh_options(options);
+                }
+                Event::KeyDown {
+                    scancode: Some(Scancode::L),
+                    repeat: false,
+                    ..
+                } => {
+                    let mut options = world.mesh_options();
+                    options.smooth_lighting = !options.smooth_lighting;
+                    world.set_mesh_options(options);
+                }
+                Event::KeyDown {
+                    scancode: Some(Scancode::O),
+                    repeat: false,
+                    ..
+                } => {
+                    let mut options = world.mesh_options();
+                    options.ambient_occlusion = !options.ambient_occlusion;
+                    world.set_mesh_options(options);
+                }
+                Event::KeyDown {
+                    scancode: Some(Scancode::M),
+                    repeat: false,
+                    ..
+                } => {
+                    let chunk_count = world.chunk_count();
+                    let memory_usage = world.memory_usage();
+                    console.print(format!(
+                        "{} chunks using {} KiB ({} bytes per chunk)",
+                        chunk_count,
+                        memory_usage / 1024,
+                        memory_usage / chunk_count.max(1),
+                    ));
+                }
+                Event::KeyDown {
+                    scancode: Some(Scancode::Slash),
+                    repeat: false,
+                    ..
+                } => {
+                    command_line = Some(String::new());
+                    text_input.start();
                 }
-                e => handle_camera_event(&mut camera, &e),
+                Event::KeyDown {
+                    scancode: Some(Scancode::T),
+                    repeat: false,
+                    ..
+                } => {
+                    world.clock.frozen = !world.clock.frozen;
+                }
+                Event::KeyDown {
+                    scancode: Some(Scancode::V),
+                    repeat: false,
+                    ..
+                } => {
+                    player.toggle_mode();
+                    console.print(format!("Movement mode: {:?}", player.mode));
+                }
+                Event::KeyDown {
+                    scancode: Some(Scancode::C),
+                    repeat: false,
+                    ..
+                } => {
+                    first_person = !first_person;
+                    camera = create_camera(first_person, viewport.w as f32 / viewport.h.max(1) as f32);
+                    camera.set_fov(field_of_view);
+                    camera.set_target(&player.eye_position());
+                }
+                Event::KeyDown {
+                    scancode: Some(Scancode::Z),
+                    repeat: false,
+                    ..
+                } => {
+                    field_of_view = ZOOM_FIELD_OF_VIEW;
+                    camera.set_fov(field_of_view);
+                }
+                Event::KeyUp {
+                    scancode: Some(Scancode::Z),
+                    ..
+                } => {
+                    field_of_view = FIELD_OF_VIEW;
+                    camera.set_fov(field_of_view);
+                }
+                Event::KeyDown {
+                    scancode: Some(Scancode::LeftBracket),
+                    ..
+                } => {
+                    world.clock.set_time(world.clock.time() - TIME_STEP);
+                }
+                Event::KeyDown {
+                    scancode: Some(Scancode::RightBracket),
+                    ..
+                } => {
+                    world.clock.set_time(world.clock.time() + TIME_STEP);
+                }
+                Event::KeyDown {
+                    scancode: Some(scancode),
+                    repeat: false,
+                    ..
+                } if number_key(scancode).is_some() => {
+                    if let Some(&block) = placeable.get(number_key(scancode).unwrap()) {
+                        selected = Some(block);
+                        console.print(format!("Selected {}", world.registry().get(block).name));
+                    }
+                }
+                Event::MouseButtonDown {
+                    mouse_btn: MouseButton::Left,
+                    ..
+                } => {
+                    if let Some((_, hit)) = &target {
+                        world.set_block(hit.position, AIR);
+                    }
+                }
+                Event::MouseButtonDown {
+                    mouse_btn: MouseButton::Right,
+                    ..
+                } => {
+                    right_dragged = false;
+                }
+                Event::MouseButtonUp {
+                    mouse_btn: MouseButton::Right,
+                    ..
+                } => {
+                    if let (Some((ray, hit)), Some(block), false) = (&target, selected, right_dragged) {
+                        place_block(&mut world, &player, ray, hit, block);
+                    }
+                }
+                Event::MouseMotion {
+                    x,
+                    y,
+                    mousestate,
+                    ..
+                } => {
+                    mouse_position = na::Point2::new(x as f32, y as f32);
+                    if mousestate.right() {
+                        right_dragged = true;
+                    }
+                    handle_camera_event(camera.as_mut(), &mut movement, &event);
+                }
+                e => handle_camera_event(camera.as_mut(), &mut movement, &e),
             }
         }
 
+        console.update(&mut world);
+
+        let new_title = window_title(command_line.as_deref(), console.message());
+        if new_title != title {
+            // titles containing a nul character can not be shown
+            let _ = window.set_title(&new_title);
+            title = new_title;
+        }
+
         let delta = time.elapsed().as_fractional_secs();
         time = Instant::now();
-        camera.apply_movement(delta as f32);
+        let input = PlayerInput {
+            direction: camera.movement_direction(&movement).unwrap_or_else(na::Vector3::zeros),
+            faster: movement.faster,
+        };
+        player.update(&world, world.registry(), &input, delta as f32);
+        camera.set_target(&player.eye_position());
+        world.clock.advance(delta as f32);
 
         let view_matrix = camera.get_view_matrix();
         let projection_matrix = camera.get_projection_matrix();
@@ -108,42 +334,118 @@ fn run() -> Result<(), failure::Error> {
             gl.Enable(gl::DEPTH_TEST);
         }
 
+        color_buffer.update_color(world.clock.sky_color());
+        color_buffer.set_used(&gl);
         color_buffer.clear(&gl);
-        world.update(&gl);
-        world.draw(&gl, &view_matrix, &projection_matrix, &camera.project_pos().coords);
+        // keep running if unloaded chunks could not be saved; the world
+        // keeps them and tries again when saving on exit
+        if let Err(e) = world.update(&gl, &player.eye_position()) {
+            console.print(debug::failure_to_string(e));
+        }
+        world.draw(&gl, &view_matrix, &projection_matrix, &camera.position().coords);
+
+        let viewport_size = na::Vector2::new(viewport.w as f32, viewport.h as f32);
+        target = Ray::from_screen(mouse_position, viewport_size, &view_matrix, &projection_matrix)
+            .and_then(|ray| world.raycast(&ray, REACH).map(|hit| (ray, hit)));
+        if let Some((_, hit)) = &target {
+            let bounds = world.registry().get(hit.block).bounds(hit.block.state());
+            let corner = na::Vector3::new(hit.position.x as f32, hit.position.y as f32, hit.position.z as f32);
+            outline.draw(&gl, &view_matrix, &projection_matrix, &(bounds.min + corner), &(bounds.max + corner));
+        }
 
         window.gl_swap_window();
     }
 
+    world.save()?;
+
     Ok(())
 }
 
-fn handle_camera_event(camera: &mut camera::TargetCamera, e: &sdl2::event::Event) {
+/// Place a block against the face of the block hit by a ray, unless the
+/// cell is already taken by a solid block or the block would be placed
+/// inside the walking player.
+fn place_block(world: &mut World, player: &Player, ray: &Ray, hit: &RaycastHit, block: Block) {
+    let position = hit.position + hit.face.normal();
+    match world.get_block(position) {
+        Some(existing) if !world.registry().get(existing).solid => {}
+        _ => return,
+    }
+
+    // how far up the face of the hit block the ray struck it
+    let height = ray.at(hit.distance).z - hit.position.z as f32;
+    let block_type = world.registry().get(block);
+    let state = block_type.placement_state(hit.face, height);
+
+    if player.mode == MovementMode::Walking && block_type.solid {
+        let bounds = block_type.bounds(state);
+        let corner = na::Vector3::new(position.x as f32, position.y as f32, position.z as f32);
+        if Aabb::new(bounds.min + corner, bounds.max + corner).intersects(&player.bounds()) {
+            return;
+        }
+    }
+
+    world.set_block(position, block.with_state(state));
+}
+
+/// Title of the window, showing the command being typed, or else the first
+/// line of the latest console message.
+fn window_title(command_line: Option<&str>, message: Option<&str>) -> String {
+    match (command_line, message.and_then(|message| message.lines().next())) {
+        (Some(line), _) => format!("Game > {}", line),
+        (None, Some(message)) => format!("Game - {}", message),
+        (None, None) => "Game".into(),
+    }
+}
+
+/// Index of a number key along the top of the keyboard, from 1 to 9 then 0.
+fn number_key(scancode: Scancode) -> Option<usize> {
+    let keys = [
+        Scancode::Num1, Scancode::Num2, Scancode::Num3, Scancode::Num4, Scancode::Num5,
+        Scancode::Num6, Scancode::Num7, Scancode::Num8, Scancode::Num9, Scancode::Num0,
+    ];
+
+    keys.iter().position(|&key| key == scancode)
+}
+
+/// Create either the first person camera, or the camera orbiting the player.
+fn create_camera(first_person: bool, aspect: f32) -> Box<dyn Camera> {
+    if first_person {
+        Box::new(FirstPersonCamera::new(aspect, FIELD_OF_VIEW, Z_NEAR, Z_FAR))
+    } else {
+        Box::new(TargetCamera::new(aspect, FIELD_OF_VIEW, Z_NEAR, Z_FAR, std::f32::consts::FRAC_PI_4, 6.0))
+    }
+}
+
+fn handle_camera_event(
+    camera: &mut dyn Camera,
+    movement: &mut NoClipMovement,
+    e: &sdl2::event::Event,
+) {
     match *e {
         Event::KeyDown {
             scancode: Some(scancode),
             ..
         } => match scancode {
-            Scancode::LCtrl | Scancode::RCtrl => camera.movement.faster = true,
-            Scancode::A => camera.movement.left = true,
-            Scancode::W => camera.movement.forward = true,
-            Scancode::S => camera.movement.backward = true,
-            Scancode::D => camera.movement.right = true,
-            Scancode::Space => camera.movement.up = true,
-            Scancode::LShift | Scancode::RShift => camera.movement.down = true,
+            Scancode::LCtrl | Scancode::RCtrl => movement.faster = true,
+            Scancode::A => movement.left = true,
+            Scancode::W => movement.forward = true,
+            Scancode::S => movement.backward = true,
+            Scancode::D => movement.right = true,
+            Scancode::Space => movement.up = true,
+            Scancode::LShift | Scancode::RShift => movement.down = true,
             _ => (),
         },
         Event::KeyUp {
             scancode: Some(scancode),
             ..
         } => match scancode {
-            Scancode::LCtrl | Scancode::RCtrl => camera.movement.faster = false,
-            Scancode::A => camera.movement.left = false,
-            Scancode::W => camera.movement.forward = false,
-            Scancode::S => camera.movement.backward = false,
-            Scancode::D => camera.movement.right = false,
-            Scancode::Space => camera.movement.up = false,
-            Scancode::LShift | Scancode::RShift => camera.movement.down = false,
+            Scancode::LCtrl | Scancode::RCtrl => movement.faster = false,
+            Scancode::A => movement.left = false,
+            Scancode::W => movement.forward = false,
+            Scancode::S => movement.backward = false,
+            Scancode::D => movement.right = false,
+            Scancode::Space => movement.up = false,
+            Scancode::LShift | Scancode::RShift => movement.down = false,
             _ => (),
         },
         Event::MouseMotion {
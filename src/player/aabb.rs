@@ -0,0 +1,64 @@
+use nalgebra as na;
+
+/// Distance within which two boxes are considered to be touching, so that
+/// rounding errors do not let a box slip into one it is resting against.
+const EPSILON: f32 = 1.0e-4;
+
+/// An axis-aligned bounding box in world space.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: na::Point3<f32>,
+    pub max: na::Point3<f32>,
+}
+
+impl Aabb {
+    pub fn new(min: na::Point3<f32>, max: na::Point3<f32>) -> Aabb {
+        Aabb { min, max }
+    }
+
+    pub fn translate(&self, offset: &na::Vector3<f32>) -> Aabb {
+        Aabb::new(self.min + offset, self.max + offset)
+    }
+
+    /// Grow the box in the direction of a movement, covering every position
+    /// it passes through.
+    pub fn expand(&self, movement: &na::Vector3<f32>) -> Aabb {
+        let mut expanded = *self;
+        for axis in 0..3 {
+            if movement[axis] < 0.0 {
+                expanded.min[axis] += movement[axis];
+            } else {
+                expanded.max[axis] += movement[axis];
+            }
+        }
+        expanded
+    }
+
+    /// Determine if the boxes overlap, rather than just touch.
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        (0..3).all(|axis| self.overlaps_on(other, axis))
+    }
+
+    /// Shorten a movement along one axis so that this box stops against the
+    /// other one instead of passing into it.
+    pub fn clip_movement(&self, other: &Aabb, axis: usize, movement: f32) -> f32 {
+        let blocked = (0..3)
+            .filter(|&other_axis| other_axis != axis)
+            .all(|other_axis| self.overlaps_on(other, other_axis));
+        if !blocked {
+            return movement;
+        }
+
+        if movement > 0.0 && other.min[axis] >= self.max[axis] - EPSILON {
+            movement.min((other.min[axis] - self.max[axis]).max(0.0))
+        } else if movement < 0.0 && other.max[axis] <= self.min[axis] + EPSILON {
+            movement.max((other.max[axis] - self.min[axis]).min(0.0))
+        } else {
+            movement
+        }
+    }
+
+    fn overlaps_on(&self, other: &Aabb, axis: usize) -> bool {
+        self.min[axis] < other.max[axis] - EPSILON && self.max[axis] > other.min[axis] + EPSILON
+    }
+}
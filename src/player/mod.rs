@@ -0,0 +1,5 @@
+pub use aabb::Aabb;
+pub use player::{MovementMode, Player, PlayerInput};
+
+mod aabb;
+mod player;
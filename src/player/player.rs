@@ -0,0 +1,330 @@
+use nalgebra as na;
+
+use crate::player::Aabb;
+use crate::world::{BlockAccess, BlockPos};
+use crate::world::block::{BlockBounds, BlockRegistry};
+
+/// Half of the width of the player along the x and y axes.
+const HALF_WIDTH: f32 = 0.3;
+const HEIGHT: f32 = 1.8;
+/// Height of the eyes above the feet.
+const EYE_HEIGHT: f32 = 1.62;
+
+const WALK_SPEED: f32 = 4.3;
+const RUN_SPEED: f32 = 7.0;
+const FLY_SPEED: f32 = 5.0;
+const FLY_FAST_SPEED: f32 = 15.0;
+/// Vertical speed when leaving the ground, enough to clear one block.
+const JUMP_SPEED: f32 = 9.0;
+const GRAVITY: f32 = 32.0;
+const TERMINAL_VELOCITY: f32 = 60.0;
+/// Longest step simulated at once, so a slow frame does not launch the
+/// player through the terrain at high speed.
+const MAX_DELTA: f32 = 0.1;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MovementMode {
+    /// Walk on the terrain, falling under gravity and colliding with solid
+    /// blocks.
+    Walking,
+    /// Fly freely through the terrain.
+    NoClip,
+}
+
+/// Movement requested by the player for a single update.
+pub struct PlayerInput {
+    /// Direction in world space. X and Y are the horizontal movement, and Z
+    /// is up to jump or fly upwards, or down to fly downwards.
+    pub direction: na::Vector3<f32>,
+    pub faster: bool,
+}
+
+pub struct Player {
+    /// Position of the centre of the player's feet.
+    pub position: na::Point3<f32>,
+    pub velocity: na::Vector3<f32>,
+    pub mode: MovementMode,
+    on_ground: bool,
+}
+
+impl Player {
+    pub fn new(position: na::Point3<f32>, mode: MovementMode) -> Player {
+        Player {
+            position,
+            velocity: na::Vector3::zeros(),
+            mode,
+            on_ground: false,
+        }
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        Aabb::new(
+            self.position + na::Vector3::new(-HALF_WIDTH, -HALF_WIDTH, 0.0),
+            self.position + na::Vector3::new(HALF_WIDTH, HALF_WIDTH, HEIGHT),
+        )
+    }
+
+    pub fn eye_position(&self) -> na::Point3<f32> {
+        self.position + na::Vector3::z() * EYE_HEIGHT
+    }
+
+    pub fn on_ground(&self) -> bool {
+        self.on_ground
+    }
+
+    pub fn toggle_mode(&mut self) {
+        self.mode = match self.mode {
+            MovementMode::Walking => MovementMode::NoClip,
+            MovementMode::NoClip => MovementMode::Walking,
+        };
+        self.velocity = na::Vector3::zeros();
+        self.on_ground = false;
+    }
+
+    /// Move the player according to the input over the time since the last
+    /// update.
+    pub fn update<A: BlockAccess>(
+        &mut self,
+        access: &A,
+        registry: &BlockRegistry,
+        input: &PlayerInput,
+        delta: f32,
+    ) {
+        let delta = delta.min(MAX_DELTA);
+
+        match self.mode {
+            MovementMode::Walking => self.walk(access, registry, input, delta),
+            MovementMode::NoClip => {
+                let speed = if input.faster { FLY_FAST_SPEED } else { FLY_SPEED };
+                self.position += input.direction * speed * delta;
+            }
+        }
+    }
+
+    fn walk<A: BlockAccess>(
+        &mut self,
+        access: &A,
+        registry: &BlockRegistry,
+        input: &PlayerInput,
+        delta: f32,
+    ) {
+        // walk at full speed whether or not jumping at the same time
+        let speed = if input.faster { RUN_SPEED } else { WALK_SPEED };
+        let horizontal = na::Vector2::new(input.direction.x, input.direction.y)
+            .try_normalize(0.01)
+            .unwrap_or_else(na::Vector2::zeros);
+        self.velocity.x = horizontal.x * speed;
+        self.velocity.y = horizontal.y * speed;
+
+        if self.on_ground && input.direction.z > 0.0 {
+            self.velocity.z = JUMP_SPEED;
+        }
+        self.velocity.z = (self.velocity.z - GRAVITY * delta).max(-TERMINAL_VELOCITY);
+
+        let movement = self.velocity * delta;
+        let moved = self.move_by(access, registry, &movement);
+
+        for axis in 0..3 {
+            if moved[axis] != movement[axis] {
+                self.velocity[axis] = 0.0;
+            }
+        }
+        self.on_ground = movement.z < 0.0 && moved.z > movement.z;
+    }
+
+    /// Sweep the player's bounds through a movement, one axis at a time,
+    /// stopping against solid blocks. Returns the distance actually moved.
+    fn move_by<A: BlockAccess>(
+        &mut self,
+        access: &A,
+        registry: &BlockRegistry,
+        movement: &na::Vector3<f32>,
+    ) -> na::Vector3<f32> {
+        let mut bounds = self.bounds();
+        let obstacles = solid_boxes(access, registry, &bounds.expand(movement));
+
+        let mut moved = na::Vector3::zeros();
+        // resolve vertical movement first, so landing on the ground is not
+        // mistaken for walking into a wall
+        for &axis in &[2, 0, 1] {
+            let distance = obstacles.iter().fold(movement[axis], |distance, obstacle| {
+                bounds.clip_movement(obstacle, axis, distance)
+            });
+
+            let mut offset = na::Vector3::zeros();
+            offset[axis] = distance;
+            bounds = bounds.translate(&offset);
+            moved[axis] = distance;
+        }
+
+        self.position += moved;
+        moved
+    }
+}
+
+/// Collect the boxes of every solid block touching the region. Blocks which
+/// are not available, such as in chunks that have not loaded yet, are treated
+/// as solid so the player cannot fall out of the world.
+fn solid_boxes<A: BlockAccess>(access: &A, registry: &BlockRegistry, region: &Aabb) -> Vec<Aabb> {
+    let min = BlockPos::from(&region.min);
+    let max = BlockPos::from(&region.max);
+
+    let mut boxes = Vec::new();
+    for z in min.z..=max.z {
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                let position = BlockPos::new(x, y, z);
+                let corner = na::Vector3::new(x as f32, y as f32, z as f32);
+
+                let bounds = match access.get_block(position) {
+                    Some(block) => {
+                        let block_type = registry.get(block);
+                        if !block_type.solid {
+                            continue;
+                        }
+                        block_type.bounds(block.state())
+                    }
+                    None => BlockBounds::full(),
+                };
+                boxes.push(Aabb::new(bounds.min + corner, bounds.max + corner));
+            }
+        }
+    }
+    boxes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{ChunkData, ChunkPos};
+    use crate::world::block::{AIR, Block};
+
+    const FRAME: f32 = 1.0 / 60.0;
+
+    fn registry() -> BlockRegistry {
+        include_str!("../../assets/blocks.ron").parse().unwrap()
+    }
+
+    /// The eight chunks meeting at the origin, so that scenes around the
+    /// origin cross chunk borders. Blocks in any other chunk are not
+    /// available.
+    struct Chunks(Vec<(ChunkPos, ChunkData<Block>)>);
+
+    impl BlockAccess for Chunks {
+        fn get_block(&self, position: BlockPos) -> Option<Block> {
+            let (chunk_position, local_position) = position.split();
+
+            self.0.iter()
+                .find(|(position, _)| *position == chunk_position)
+                .map(|(_, block_data)| block_data[local_position])
+        }
+
+        fn set_block(&mut self, position: BlockPos, block: Block) -> bool {
+            let (chunk_position, local_position) = position.split();
+
+            match self.0.iter_mut().find(|(position, _)| *position == chunk_position) {
+                Some((_, block_data)) => {
+                    block_data.set(local_position, block);
+                    true
+                }
+                None => false,
+            }
+        }
+    }
+
+    /// A stone floor with its top at z = 0, and stone wherever `solid` is true
+    /// above it.
+    fn scene(registry: &BlockRegistry, solid: impl Fn(i64, i64, i64) -> bool) -> Chunks {
+        let stone = registry.require("stone").unwrap();
+
+        let mut chunks = Chunks(Vec::new());
+        for x in -1..=0 {
+            for y in -1..=0 {
+                for z in -1..=0 {
+                    chunks.0.push((ChunkPos::new(x, y, z), ChunkData::new(AIR)));
+                }
+            }
+        }
+
+        for x in -4..=4 {
+            for y in -4..=4 {
+                chunks.set_block(BlockPos::new(x, y, -1), stone);
+                for z in 0..4 {
+                    if solid(x, y, z) {
+                        chunks.set_block(BlockPos::new(x, y, z), stone);
+                    }
+                }
+            }
+        }
+        chunks
+    }
+
+    /// Update the player for a number of frames, checking that it never ends
+    /// up inside a solid block.
+    fn tick(player: &mut Player, blocks: &Chunks, registry: &BlockRegistry, direction: na::Vector3<f32>, frames: usize) {
+        let input = PlayerInput { direction, faster: false };
+
+        for _ in 0..frames {
+            player.update(blocks, registry, &input, FRAME);
+
+            let bounds = player.bounds();
+            for solid in solid_boxes(blocks, registry, &bounds) {
+                assert!(!solid.intersects(&bounds), "player at {:?} is inside {:?}", player.position, solid);
+            }
+        }
+    }
+
+    fn walking_player(x: f32, y: f32, z: f32) -> Player {
+        Player::new(na::Point3::new(x, y, z), MovementMode::Walking)
+    }
+
+    #[test]
+    fn falls_onto_floor() {
+        let registry = registry();
+        let blocks = scene(&registry, |_, _, _| false);
+        let mut player = walking_player(0.5, 0.5, 3.0);
+
+        tick(&mut player, &blocks, &registry, na::Vector3::zeros(), 60);
+
+        assert!((player.position - na::Point3::new(0.5, 0.5, 0.0)).norm() < 1e-3, "at {:?}", player.position);
+        assert!(player.on_ground());
+        assert_eq!(player.velocity, na::Vector3::zeros());
+    }
+
+    #[test]
+    fn stops_against_wall() {
+        let registry = registry();
+        let blocks = scene(&registry, |x, _, _| x == 3);
+        let mut player = walking_player(0.5, 0.5, 0.0);
+
+        tick(&mut player, &blocks, &registry, na::Vector3::x(), 60);
+
+        assert!((player.position.x - (3.0 - HALF_WIDTH)).abs() < 1e-3, "at {:?}", player.position);
+        assert!((player.position.y - 0.5).abs() < 1e-3);
+        assert!(player.position.z.abs() < 1e-3);
+        assert!(player.on_ground());
+    }
+
+    #[test]
+    fn jump_is_stopped_by_ceiling() {
+        let registry = registry();
+        // a ceiling leaving a gap of 0.2 above the player's head
+        let blocks = scene(&registry, |_, _, z| z == 2);
+        let mut player = walking_player(0.5, 0.5, 0.0);
+        tick(&mut player, &blocks, &registry, na::Vector3::zeros(), 1);
+        assert!(player.on_ground());
+
+        tick(&mut player, &blocks, &registry, na::Vector3::z(), 1);
+        assert!(!player.on_ground());
+
+        let mut highest = player.position.z;
+        for _ in 0..30 {
+            tick(&mut player, &blocks, &registry, na::Vector3::zeros(), 1);
+            highest = highest.max(player.position.z);
+        }
+
+        assert!((highest - (2.0 - HEIGHT)).abs() < 1e-3, "reached {}", highest);
+        assert!(player.position.z.abs() < 1e-3, "at {:?}", player.position);
+        assert!(player.on_ground());
+    }
+}
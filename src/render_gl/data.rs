@@ -161,6 +161,45 @@ impl From<u8> for u8_ {
     }
 }
 
+/// Four 4-bit unsigned values packed into a `u16`, passed to the shader as an
+/// unsigned integer for it to unpack.
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone, Debug)]
+#[repr(C, packed)]
+pub struct u4_u4_u4_u4_int {
+    pub inner: u16,
+}
+
+impl u4_u4_u4_u4_int {
+    pub fn new(d0: u16) -> u4_u4_u4_u4_int {
+        u4_u4_u4_u4_int {
+            inner: d0,
+        }
+    }
+
+    pub unsafe fn vertex_attrib_pointer(
+        gl: &gl::Gl,
+        stride: usize,
+        location: usize,
+        offset: usize,
+    ) {
+        gl.EnableVertexAttribArray(location as gl::types::GLuint);
+        gl.VertexAttribIPointer(
+            location as gl::types::GLuint,
+            1, // the number of components per generic vertex attribute
+            gl::UNSIGNED_SHORT, // data type
+            stride as gl::types::GLint,
+            offset as *const gl::types::GLvoid,
+        );
+    }
+}
+
+impl From<u16> for u4_u4_u4_u4_int {
+    fn from(other: u16) -> Self {
+        u4_u4_u4_u4_int::new(other)
+    }
+}
+
 #[allow(non_camel_case_types)]
 #[derive(Copy, Clone, Debug)]
 #[repr(C, packed)]
@@ -1,6 +1,6 @@
 pub use self::color_buffer::ColorBuffer;
 pub use self::shader::{Error, Program, Shader};
-pub use self::texture::Texture;
+pub use self::texture::{Texture, TextureAtlas};
 pub use self::viewport::Viewport;
 
 pub mod buffer;
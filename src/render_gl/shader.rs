@@ -154,6 +154,25 @@ impl Program {
         }
     }
 
+    pub fn set_uniform_2f(&self, location: i32, value: &na::Vector2<f32>) {
+        unsafe {
+            self.gl.Uniform2fv(
+                location,
+                1,
+                value.as_slice().as_ptr() as *const f32,
+            );
+        }
+    }
+
+    pub fn set_uniform_1f(&self, location: i32, value: f32) {
+        unsafe {
+            self.gl.Uniform1f(
+                location,
+                value,
+            );
+        }
+    }
+
     pub fn set_uniform_1i(&self, location: i32, value: i32) {
         unsafe {
             self.gl.Uniform1i(
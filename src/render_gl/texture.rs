@@ -51,6 +51,15 @@ impl<'a> TextureOptions<'a> {
 pub struct Texture {
     gl: gl::Gl,
     obj: gl::types::GLuint,
+    atlas: TextureAtlas,
+}
+
+/// Layout of a square texture atlas made up of equally sized tiles.
+///
+/// Does not own any GL resources, so it can be shared with other threads to
+/// calculate texture coordinates.
+#[derive(Copy, Clone, Debug)]
+pub struct TextureAtlas {
     uv_size: f32,
 }
 
@@ -76,7 +85,7 @@ impl Texture {
         let texture = Texture {
             gl: gl.clone(),
             obj,
-            uv_size: 1.0 / options.rows as f32,
+            atlas: TextureAtlas::new(options.rows),
         };
 
         texture.update(options, res)?;
@@ -199,6 +208,22 @@ impl Texture {
         self.bind();
     }
 
+    pub fn atlas(&self) -> TextureAtlas {
+        self.atlas
+    }
+
+    pub fn uv_from_index(&self, index: u32) -> [f16_f16; 4] {
+        self.atlas.uv_from_index(index)
+    }
+}
+
+impl TextureAtlas {
+    pub fn new(rows: u16) -> TextureAtlas {
+        TextureAtlas {
+            uv_size: 1.0 / rows as f32,
+        }
+    }
+
     pub fn uv_from_index(&self, index: u32) -> [f16_f16; 4] {
         let rows = (1.0 / self.uv_size) as u32;
 
@@ -214,6 +239,18 @@ impl Texture {
         ]
     }
 
+    /// Size of a single tile in texture coordinates.
+    pub fn tile_size(&self) -> f32 {
+        self.uv_size
+    }
+
+    /// Texture coordinates of the top left corner of a tile.
+    pub fn tile_origin(&self, index: u32) -> f16_f16 {
+        let rows = (1.0 / self.uv_size) as u32;
+
+        self.uv_from_x_y(index % rows, index / rows)
+    }
+
     fn uv_from_x_y(&self, x: u32, y: u32) -> f16_f16 {
         f16_f16::from((
             self.uv_size * x as f32,
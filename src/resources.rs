@@ -10,6 +10,8 @@ pub enum Error {
     Io(#[cause] io::Error),
     #[fail(display = "Failed to read CString from file that contains null byte")]
     FileContainsNil,
+    #[fail(display = "File is not valid UTF-8")]
+    FileNotUtf8,
     #[fail(display = "Failed to get executable path")]
     FailedToGetExePath,
     #[fail(display = "Failed to load image")]
@@ -65,6 +67,19 @@ impl Resources {
         Ok(unsafe { ffi::CString::from_vec_unchecked(buffer) })
     }
 
+    pub fn load_string(&self, resource_name: &str) -> Result<String, Error> {
+        let mut file = fs::File::open(
+            resource_name_to_path(&self.root_path, resource_name),
+        )?;
+
+        let mut buffer: Vec<u8> = Vec::with_capacity(
+            file.metadata()?.len() as usize,
+        );
+        file.read_to_end(&mut buffer)?;
+
+        String::from_utf8(buffer).map_err(|_| Error::FileNotUtf8)
+    }
+
     pub fn load_rgb_image(&self, resource_name: &str) -> Result<image::RgbImage, Error> {
         let path = resource_name_to_path(&self.root_path, resource_name);
         let img = image::open(path)?;
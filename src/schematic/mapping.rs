@@ -0,0 +1,260 @@
+use std::fmt;
+
+use ron;
+use serde::Deserialize;
+
+use crate::resources::Resources;
+use crate::world::block::{Block, BlockRegistry};
+
+use super::Error;
+
+/// Namespace of block states written for blocks which have no entry in the
+/// mapping, so that they survive a round trip between worlds.
+const NAMESPACE: &str = "game";
+
+/// A block state as written in a schematic palette, such as
+/// `minecraft:oak_log[axis=y]`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SchematicState {
+    pub name: String,
+    pub properties: Vec<(String, String)>,
+}
+
+impl SchematicState {
+    pub fn parse(source: &str) -> SchematicState {
+        let (name, properties) = match source.find('[') {
+            Some(start) => (&source[..start], source[start + 1..].trim_end_matches(']')),
+            None => (source, ""),
+        };
+
+        SchematicState {
+            name: name.into(),
+            properties: properties.split(',')
+                .filter_map(|property| {
+                    let mut parts = property.splitn(2, '=');
+                    Some((parts.next()?.trim().into(), parts.next()?.trim().into()))
+                })
+                .collect(),
+        }
+    }
+
+    pub fn property(&self, name: &str) -> Option<&str> {
+        self.properties.iter()
+            .find(|(property, _)| property == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Determine if this state has every property of `other` with the same
+    /// value, and the same name.
+    fn matches(&self, other: &SchematicState) -> bool {
+        self.name == other.name
+            && other.properties.iter().all(|(name, value)| self.property(name) == Some(value))
+    }
+}
+
+impl fmt::Display for SchematicState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+
+        if !self.properties.is_empty() {
+            let properties: Vec<String> = self.properties.iter()
+                .map(|(name, value)| format!("{}={}", name, value))
+                .collect();
+            write!(f, "[{}]", properties.join(","))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+struct MappingEntry {
+    schematic: String,
+    block: String,
+    #[serde(default)]
+    state: u8,
+}
+
+/// Table translating between the block states of schematics and registered
+/// blocks.
+///
+/// Blocks with no entry are written in the `game` namespace with their
+/// registry name and state, like `game:red_lamp`, and read back the same way.
+pub struct BlockMapping {
+    entries: Vec<(SchematicState, Block)>,
+}
+
+impl BlockMapping {
+    /// Load the mapping from a RON resource containing a list of entries,
+    /// each with a schematic block state and the name and state of the block
+    /// it maps to.
+    pub fn from_res(res: &Resources, name: &str, registry: &BlockRegistry) -> Result<BlockMapping, Error> {
+        let source = res.load_string(name)
+            .map_err(|e| Error::ResourceLoad { name: name.into(), inner: e })?;
+
+        BlockMapping::from_str(&source, registry)
+            .map_err(|e| match e {
+                Error::Parse { inner, .. } => Error::Parse { name: name.into(), inner },
+                e => e,
+            })
+    }
+
+    pub fn from_str(source: &str, registry: &BlockRegistry) -> Result<BlockMapping, Error> {
+        let entries: Vec<MappingEntry> = ron::de::from_str(source)
+            .map_err(|e| Error::Parse { name: String::new(), inner: e })?;
+
+        let entries = entries.into_iter()
+            .map(|entry| {
+                let block = registry.id(&entry.block)
+                    .ok_or_else(|| Error::UnknownBlock { name: entry.block.clone() })?;
+                if !registry.get(block).is_valid_state(entry.state) {
+                    return Err(Error::InvalidState { name: entry.block, state: entry.state });
+                }
+
+                Ok((SchematicState::parse(&entry.schematic), block.with_state(entry.state)))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(BlockMapping {
+            entries,
+        })
+    }
+
+    /// Find the block for a schematic block state, or `None` if there is no
+    /// such block.
+    ///
+    /// Properties of the state which the mapping does not mention are
+    /// ignored, and the entry with the most matching properties is used.
+    pub fn to_block(&self, registry: &BlockRegistry, state: &SchematicState) -> Option<Block> {
+        let mapped = self.entries.iter()
+            .filter(|(entry, _)| state.matches(entry))
+            .max_by_key(|(entry, _)| entry.properties.len())
+            .map(|&(_, block)| block);
+        if mapped.is_some() {
+            return mapped;
+        }
+
+        let name = state.name.strip_prefix(NAMESPACE)?.strip_prefix(':')?;
+        let block = registry.id(name)?;
+        let block_state = match state.property("state") {
+            Some(block_state) => block_state.parse().ok()?,
+            None => 0,
+        };
+
+        if registry.get(block).is_valid_state(block_state) {
+            Some(block.with_state(block_state))
+        } else {
+            None
+        }
+    }
+
+    /// Get the schematic block state for a block: the first entry in the
+    /// mapping for the block, or its name and state in the `game` namespace.
+    pub fn to_schematic(&self, registry: &BlockRegistry, block: Block) -> SchematicState {
+        if let Some((state, _)) = self.entries.iter().find(|&&(_, entry)| entry == block) {
+            return state.clone();
+        }
+
+        let properties = match block.state() {
+            0 => Vec::new(),
+            state => vec![("state".into(), state.to_string())],
+        };
+
+        SchematicState {
+            name: format!("{}:{}", NAMESPACE, registry.get(block).name),
+            properties,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry() -> BlockRegistry {
+        include_str!("../../assets/blocks.ron").parse().unwrap()
+    }
+
+    fn mapping(registry: &BlockRegistry) -> BlockMapping {
+        BlockMapping::from_str(include_str!("../../assets/schematic_blocks.ron"), registry).unwrap()
+    }
+
+    #[test]
+    fn states_are_parsed_and_written() {
+        let state = SchematicState::parse("minecraft:oak_log[axis=x, waterlogged=false]");
+
+        assert_eq!(state.name, "minecraft:oak_log");
+        assert_eq!(state.property("axis"), Some("x"));
+        assert_eq!(state.property("waterlogged"), Some("false"));
+        assert_eq!(state.property("age"), None);
+        assert_eq!(state.to_string(), "minecraft:oak_log[axis=x,waterlogged=false]");
+        assert_eq!(SchematicState::parse("minecraft:stone").to_string(), "minecraft:stone");
+    }
+
+    #[test]
+    fn unlisted_properties_are_ignored() {
+        let registry = registry();
+        let mapping = mapping(&registry);
+        let log = registry.require("log").unwrap();
+
+        let state = SchematicState::parse("minecraft:oak_log[axis=x,waterlogged=false]");
+        assert_eq!(mapping.to_block(&registry, &state), Some(log.with_state(1)));
+
+        let state = SchematicState::parse("minecraft:stone[snowy=true]");
+        assert_eq!(mapping.to_block(&registry, &state), registry.id("stone"));
+    }
+
+    #[test]
+    fn mapped_blocks_round_trip() {
+        let registry = registry();
+        let mapping = mapping(&registry);
+
+        for block in [("log", 2), ("stone_slab", 1), ("wheat", 7), ("torch", 0)].iter()
+            .map(|&(name, state)| registry.require(name).unwrap().with_state(state))
+        {
+            let state = mapping.to_schematic(&registry, block);
+
+            assert!(state.name.starts_with("minecraft:"), "{}", state);
+            assert_eq!(mapping.to_block(&registry, &state), Some(block));
+        }
+    }
+
+    #[test]
+    fn unmapped_blocks_use_the_game_namespace() {
+        let registry = registry();
+        let mapping = mapping(&registry);
+        let lamp = registry.require("red_lamp").unwrap();
+
+        let state = mapping.to_schematic(&registry, lamp);
+        assert_eq!(state.to_string(), "game:red_lamp");
+        assert_eq!(mapping.to_block(&registry, &state), Some(lamp));
+
+        let log = registry.require("log").unwrap();
+        let state = SchematicState::parse("game:log[state=1]");
+        assert_eq!(mapping.to_block(&registry, &state), Some(log.with_state(1)));
+    }
+
+    #[test]
+    fn unknown_blocks_and_states_are_not_mapped() {
+        let registry = registry();
+        let mapping = mapping(&registry);
+
+        for state in &["minecraft:diamond_block", "game:diamond_block", "game:log[state=9]", "game:log[state=x]"] {
+            assert_eq!(mapping.to_block(&registry, &SchematicState::parse(state)), None, "{}", state);
+        }
+    }
+
+    #[test]
+    fn mappings_to_unknown_blocks_are_rejected() {
+        let registry = registry();
+
+        match BlockMapping::from_str(r#"[(schematic: "minecraft:stone", block: "marble")]"#, &registry) {
+            Err(Error::UnknownBlock { name }) => assert_eq!(name, "marble"),
+            result => panic!("expected an unknown block error, got {:?}", result.err()),
+        }
+        match BlockMapping::from_str(r#"[(schematic: "minecraft:stone", block: "stone", state: 3)]"#, &registry) {
+            Err(Error::InvalidState { name, state }) => assert_eq!((name.as_str(), state), ("stone", 3)),
+            result => panic!("expected an invalid state error, got {:?}", result.err()),
+        }
+    }
+}
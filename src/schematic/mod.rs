@@ -0,0 +1,11 @@
+//! Copying regions of the world and saving them as Sponge schematics, to be
+//! pasted into other worlds.
+
+pub use mapping::{BlockMapping, SchematicState};
+pub use schematic::{Error, Schematic};
+pub use transform::{Mirror, Rotation, Transform};
+
+mod mapping;
+pub mod nbt;
+mod schematic;
+mod transform;
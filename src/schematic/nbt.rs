@@ -0,0 +1,354 @@
+//! A minimal reader and writer for Minecraft's Named Binary Tag format, as
+//! used by schematics. Values are big endian.
+
+use std::convert::TryInto;
+
+/// Deepest nesting of lists and compounds accepted when reading.
+const MAX_DEPTH: usize = 512;
+
+#[derive(Debug, Fail)]
+pub enum NbtError {
+    #[fail(display = "NBT data ends unexpectedly")]
+    Truncated,
+    #[fail(display = "NBT data contains unknown tag type {}", id)]
+    UnknownTag { id: u8 },
+    #[fail(display = "NBT data has a negative length")]
+    NegativeLength,
+    #[fail(display = "NBT data is nested too deeply")]
+    TooDeep,
+    #[fail(display = "NBT root is not a compound")]
+    RootNotCompound,
+    #[fail(display = "NBT string is {} bytes long, more than 65535", len)]
+    StringTooLong { len: usize },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<i8>),
+    String(String),
+    List(Vec<Tag>),
+    /// Named tags, in the order they were read or added.
+    Compound(Vec<(String, Tag)>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl Tag {
+    fn id(&self) -> u8 {
+        match self {
+            Tag::Byte(_) => 1,
+            Tag::Short(_) => 2,
+            Tag::Int(_) => 3,
+            Tag::Long(_) => 4,
+            Tag::Float(_) => 5,
+            Tag::Double(_) => 6,
+            Tag::ByteArray(_) => 7,
+            Tag::String(_) => 8,
+            Tag::List(_) => 9,
+            Tag::Compound(_) => 10,
+            Tag::IntArray(_) => 11,
+            Tag::LongArray(_) => 12,
+        }
+    }
+
+    /// Get a tag in a compound by name, or `None` if this is not a compound
+    /// or has no such tag.
+    pub fn get(&self, name: &str) -> Option<&Tag> {
+        match self {
+            Tag::Compound(tags) => tags.iter()
+                .find(|(tag_name, _)| tag_name == name)
+                .map(|(_, tag)| tag),
+            _ => None,
+        }
+    }
+}
+
+/// Read the root compound of NBT data, returning its name and the compound.
+pub fn read(bytes: &[u8]) -> Result<(String, Tag), NbtError> {
+    let mut reader = Reader { bytes, depth: 0 };
+
+    if reader.u8()? != 10 {
+        return Err(NbtError::RootNotCompound);
+    }
+    let name = reader.string()?;
+    let root = reader.payload(10)?;
+
+    Ok((name, root))
+}
+
+/// Write a root compound with the given name.
+pub fn write(name: &str, root: &Tag) -> Result<Vec<u8>, NbtError> {
+    let mut bytes = Vec::new();
+    bytes.push(root.id());
+    write_string(&mut bytes, name)?;
+    write_payload(&mut bytes, root)?;
+    Ok(bytes)
+}
+
+fn write_string(bytes: &mut Vec<u8>, value: &str) -> Result<(), NbtError> {
+    let len: u16 = value.len().try_into()
+        .map_err(|_| NbtError::StringTooLong { len: value.len() })?;
+
+    bytes.extend_from_slice(&len.to_be_bytes());
+    bytes.extend_from_slice(value.as_bytes());
+    Ok(())
+}
+
+fn write_payload(bytes: &mut Vec<u8>, tag: &Tag) -> Result<(), NbtError> {
+    match tag {
+        Tag::Byte(value) => bytes.push(*value as u8),
+        Tag::Short(value) => bytes.extend_from_slice(&value.to_be_bytes()),
+        Tag::Int(value) => bytes.extend_from_slice(&value.to_be_bytes()),
+        Tag::Long(value) => bytes.extend_from_slice(&value.to_be_bytes()),
+        Tag::Float(value) => bytes.extend_from_slice(&value.to_be_bytes()),
+        Tag::Double(value) => bytes.extend_from_slice(&value.to_be_bytes()),
+        Tag::ByteArray(values) => {
+            bytes.extend_from_slice(&(values.len() as i32).to_be_bytes());
+            bytes.extend(values.iter().map(|&value| value as u8));
+        }
+        Tag::String(value) => write_string(bytes, value)?,
+        Tag::List(tags) => {
+            // empty lists are written as lists of end tags
+            bytes.push(tags.first().map_or(0, Tag::id));
+            bytes.extend_from_slice(&(tags.len() as i32).to_be_bytes());
+            for tag in tags {
+                write_payload(bytes, tag)?;
+            }
+        }
+        Tag::Compound(tags) => {
+            for (name, tag) in tags {
+                bytes.push(tag.id());
+                write_string(bytes, name)?;
+                write_payload(bytes, tag)?;
+            }
+            bytes.push(0);
+        }
+        Tag::IntArray(values) => {
+            bytes.extend_from_slice(&(values.len() as i32).to_be_bytes());
+            for value in values {
+                bytes.extend_from_slice(&value.to_be_bytes());
+            }
+        }
+        Tag::LongArray(values) => {
+            bytes.extend_from_slice(&(values.len() as i32).to_be_bytes());
+            for value in values {
+                bytes.extend_from_slice(&value.to_be_bytes());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    depth: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], NbtError> {
+        if self.bytes.len() < len {
+            return Err(NbtError::Truncated);
+        }
+
+        let (bytes, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, NbtError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn i16(&mut self) -> Result<i16, NbtError> {
+        Ok(i16::from_be_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> Result<i32, NbtError> {
+        Ok(i32::from_be_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn i64(&mut self) -> Result<i64, NbtError> {
+        Ok(i64::from_be_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    /// Read the length of an array or list, checking that the remaining data
+    /// could hold that many elements of the given size.
+    fn len(&mut self, element_size: usize) -> Result<usize, NbtError> {
+        let len = self.i32()?;
+        if len < 0 {
+            return Err(NbtError::NegativeLength);
+        }
+        if (len as usize).saturating_mul(element_size) > self.bytes.len() {
+            return Err(NbtError::Truncated);
+        }
+
+        Ok(len as usize)
+    }
+
+    fn string(&mut self) -> Result<String, NbtError> {
+        let len = u16::from_be_bytes(self.bytes(2)?.try_into().unwrap()) as usize;
+
+        // Java's modified UTF-8 only differs for characters which block
+        // names never use
+        Ok(String::from_utf8_lossy(self.bytes(len)?).into_owned())
+    }
+
+    fn payload(&mut self, id: u8) -> Result<Tag, NbtError> {
+        Ok(match id {
+            1 => Tag::Byte(self.u8()? as i8),
+            2 => Tag::Short(self.i16()?),
+            3 => Tag::Int(self.i32()?),
+            4 => Tag::Long(self.i64()?),
+            5 => Tag::Float(f32::from_bits(self.i32()? as u32)),
+            6 => Tag::Double(f64::from_bits(self.i64()? as u64)),
+            7 => {
+                let len = self.len(1)?;
+                Tag::ByteArray(self.bytes(len)?.iter().map(|&value| value as i8).collect())
+            }
+            8 => Tag::String(self.string()?),
+            9 => {
+                let element_id = self.u8()?;
+                // a list of end tags is always empty
+                if element_id == 0 {
+                    self.i32()?;
+                    return Ok(Tag::List(Vec::new()));
+                }
+                // every other payload takes at least one byte
+                let len = self.len(1)?;
+
+                self.enter()?;
+                let tags = (0..len)
+                    .map(|_| self.payload(element_id))
+                    .collect::<Result<_, _>>()?;
+                self.depth -= 1;
+
+                Tag::List(tags)
+            }
+            10 => {
+                self.enter()?;
+                let mut tags = Vec::new();
+                loop {
+                    let id = self.u8()?;
+                    if id == 0 {
+                        break;
+                    }
+                    let name = self.string()?;
+                    tags.push((name, self.payload(id)?));
+                }
+                self.depth -= 1;
+
+                Tag::Compound(tags)
+            }
+            11 => {
+                let len = self.len(4)?;
+                Tag::IntArray((0..len).map(|_| self.i32()).collect::<Result<_, _>>()?)
+            }
+            12 => {
+                let len = self.len(8)?;
+                Tag::LongArray((0..len).map(|_| self.i64()).collect::<Result<_, _>>()?)
+            }
+            id => return Err(NbtError::UnknownTag { id }),
+        })
+    }
+
+    fn enter(&mut self) -> Result<(), NbtError> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(NbtError::TooDeep);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A compound holding every type of tag.
+    fn every_tag() -> Tag {
+        Tag::Compound(vec![
+            ("byte".into(), Tag::Byte(-3)),
+            ("short".into(), Tag::Short(-300)),
+            ("int".into(), Tag::Int(70_000)),
+            ("long".into(), Tag::Long(-5_000_000_000)),
+            ("float".into(), Tag::Float(1.5)),
+            ("double".into(), Tag::Double(-0.25)),
+            ("bytes".into(), Tag::ByteArray(vec![1, -1, 127, -128])),
+            ("string".into(), Tag::String("minecraft:oak_log[axis=y]".into())),
+            ("list".into(), Tag::List(vec![Tag::Short(1), Tag::Short(2)])),
+            ("empty list".into(), Tag::List(Vec::new())),
+            ("compound".into(), Tag::Compound(vec![("nested".into(), Tag::Int(1))])),
+            ("ints".into(), Tag::IntArray(vec![i32::MIN, 0, i32::MAX])),
+            ("longs".into(), Tag::LongArray(vec![i64::MIN, 0, i64::MAX])),
+        ])
+    }
+
+    #[test]
+    fn every_tag_round_trips() {
+        let bytes = write("root", &every_tag()).unwrap();
+
+        assert_eq!(read(&bytes).unwrap(), ("root".to_string(), every_tag()));
+    }
+
+    #[test]
+    fn truncated_data_is_rejected() {
+        let bytes = write("root", &every_tag()).unwrap();
+
+        for len in 0..bytes.len() {
+            assert!(read(&bytes[..len]).is_err(), "read {} of {} bytes", len, bytes.len());
+        }
+    }
+
+    #[test]
+    fn negative_lengths_are_rejected() {
+        // a byte array named "a" with a length of -1
+        let bytes = [10, 0, 0, 7, 0, 1, b'a', 0xff, 0xff, 0xff, 0xff, 0];
+
+        match read(&bytes) {
+            Err(NbtError::NegativeLength) => {}
+            result => panic!("expected a negative length error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn roots_must_be_compounds() {
+        let bytes = write("root", &Tag::Int(1)).unwrap();
+
+        match read(&bytes) {
+            Err(NbtError::RootNotCompound) => {}
+            result => panic!("expected a root error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn deep_nesting_is_rejected() {
+        let mut tag = Tag::Compound(Vec::new());
+        for _ in 0..MAX_DEPTH {
+            tag = Tag::List(vec![tag]);
+        }
+        let bytes = write("root", &Tag::Compound(vec![("deep".into(), tag)])).unwrap();
+
+        match read(&bytes) {
+            Err(NbtError::TooDeep) => {}
+            result => panic!("expected a nesting error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn long_strings_are_not_written() {
+        let name = "a".repeat(u16::MAX as usize + 1);
+
+        match write(&name, &Tag::Compound(Vec::new())) {
+            Err(NbtError::StringTooLong { len }) => assert_eq!(len, name.len()),
+            result => panic!("expected a string length error, got {:?}", result),
+        }
+        assert!(write(&name[1..], &Tag::Compound(Vec::new())).is_ok());
+    }
+}
@@ -0,0 +1,522 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use ron;
+
+use crate::resources;
+use crate::world::{BlockAccess, BlockPos, Position};
+use crate::world::block::{AIR, Block, BlockRegistry};
+
+use super::mapping::{BlockMapping, SchematicState};
+use super::nbt::{self, NbtError, Tag};
+use super::transform::Transform;
+
+/// Version of the Sponge schematic format which is written.
+const VERSION: i32 = 2;
+/// Minecraft data version written to schematics: Minecraft 1.16.5, whose
+/// block names the mapping uses.
+const DATA_VERSION: i32 = 2586;
+/// Largest decompressed schematic accepted.
+const MAX_SCHEMATIC_SIZE: u64 = 256 << 20;
+/// Largest number of blocks in a schematic, so that copying or loading one
+/// can not exhaust memory.
+const MAX_VOLUME: i64 = 1 << 24;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "I/O error")]
+    Io(#[cause] io::Error),
+    #[fail(display = "Invalid NBT data")]
+    Nbt(#[cause] NbtError),
+    #[fail(display = "Failed to load resource {}", name)]
+    ResourceLoad { name: String, #[cause] inner: resources::Error },
+    #[fail(display = "Failed to parse block mapping {}", name)]
+    Parse { name: String, #[cause] inner: ron::de::Error },
+    #[fail(display = "Block {} is not registered", name)]
+    UnknownBlock { name: String },
+    #[fail(display = "Block {} does not have state {}", name, state)]
+    InvalidState { name: String, state: u8 },
+    #[fail(display = "Schematic is missing tag {}", name)]
+    MissingTag { name: String },
+    #[fail(display = "Schematic version {} is not supported", version)]
+    UnsupportedVersion { version: i32 },
+    #[fail(display = "Schematic block data does not match its size and palette")]
+    InvalidBlockData,
+    #[fail(display = "Block {:?} is not loaded", position)]
+    Unavailable { position: BlockPos },
+    #[fail(display = "Schematics can be at most 65535 blocks across and 16777216 blocks in total, not {:?}", size)]
+    TooLarge { size: [i64; 3] },
+}
+
+impl From<io::Error> for Error {
+    fn from(other: io::Error) -> Self {
+        Error::Io(other)
+    }
+}
+
+impl From<NbtError> for Error {
+    fn from(other: NbtError) -> Self {
+        Error::Nbt(other)
+    }
+}
+
+/// A copy of the blocks in a cuboid region of the world, which can be pasted
+/// elsewhere and saved as a Sponge schematic.
+///
+/// Minecraft, and so the schematic file, uses Y as the up axis; it is
+/// converted to Z when reading and back when writing.
+#[derive(Clone, Debug)]
+pub struct Schematic {
+    size: [i64; 3],
+    blocks: Vec<Block>,
+    /// Block states in the schematic which have no matching block, and were
+    /// read as air.
+    pub unmapped: Vec<String>,
+}
+
+impl Schematic {
+    /// Copy the blocks in the box between two corners, inclusive. Every block
+    /// in the box has to be available.
+    pub fn copy<A: BlockAccess>(access: &A, min: BlockPos, max: BlockPos) -> Result<Schematic, Error> {
+        let (min, max) = (
+            BlockPos::new(min.x.min(max.x), min.y.min(max.y), min.z.min(max.z)),
+            BlockPos::new(min.x.max(max.x), min.y.max(max.y), min.z.max(max.z)),
+        );
+
+        let size = [max.x - min.x + 1, max.y - min.y + 1, max.z - min.z + 1];
+        if size.iter().any(|&axis| axis > u16::MAX as i64) || size.iter().product::<i64>() > MAX_VOLUME {
+            return Err(Error::TooLarge { size });
+        }
+
+        let mut schematic = Schematic {
+            size,
+            blocks: Vec::with_capacity((size[0] * size[1] * size[2]) as usize),
+            unmapped: Vec::new(),
+        };
+        for z in 0..size[2] {
+            for y in 0..size[1] {
+                for x in 0..size[0] {
+                    let position = min + Position::new(x, y, z);
+                    let block = access.get_block(position).ok_or(Error::Unavailable { position })?;
+                    schematic.blocks.push(block);
+                }
+            }
+        }
+
+        Ok(schematic)
+    }
+
+    /// Size of the schematic along each axis.
+    pub fn size(&self) -> [i64; 3] {
+        self.size
+    }
+
+    /// Get the block at a position relative to the lowest corner.
+    pub fn get(&self, position: Position) -> Block {
+        self.blocks[self.index(position)]
+    }
+
+    fn index(&self, position: Position) -> usize {
+        (position.x + position.y * self.size[0] + position.z * self.size[0] * self.size[1]) as usize
+    }
+
+    /// Place the blocks of the schematic in the world, with the lowest corner
+    /// of the transformed schematic at `origin`. Returns the number of blocks
+    /// placed; parts of the world which are not available are skipped.
+    pub fn paste<A: BlockAccess>(
+        &self,
+        access: &mut A,
+        registry: &BlockRegistry,
+        origin: BlockPos,
+        transform: Transform,
+    ) -> usize {
+        let (width, length, height) = (self.size[0], self.size[1], self.size[2]);
+
+        let positions = (0..height).flat_map(move |z| {
+            (0..length).flat_map(move |y| (0..width).map(move |x| Position::new(x, y, z)))
+        });
+        access.set_blocks(positions.map(|position| (
+            origin + transform.position(self.size, position),
+            transform.block(registry, self.get(position)),
+        )))
+    }
+
+    pub fn load(path: &Path, registry: &BlockRegistry, mapping: &BlockMapping) -> Result<Schematic, Error> {
+        Schematic::from_bytes(&fs::read(path)?, registry, mapping)
+    }
+
+    pub fn save(&self, path: &Path, registry: &BlockRegistry, mapping: &BlockMapping) -> Result<(), Error> {
+        Ok(fs::write(path, self.to_bytes(registry, mapping)?)?)
+    }
+
+    /// Read a Sponge schematic of version 1, 2 or 3, compressed with gzip or
+    /// not.
+    pub fn from_bytes(bytes: &[u8], registry: &BlockRegistry, mapping: &BlockMapping) -> Result<Schematic, Error> {
+        let mut data = Vec::new();
+        if bytes.starts_with(&[0x1f, 0x8b]) {
+            GzDecoder::new(bytes).take(MAX_SCHEMATIC_SIZE).read_to_end(&mut data)?;
+        } else {
+            data.extend_from_slice(bytes);
+        }
+
+        // version 3 nests the schematic inside an unnamed root
+        let (_, root) = nbt::read(&data)?;
+        let schematic = root.get("Schematic").unwrap_or(&root);
+
+        let version = get_int(schematic, "Version")?;
+        let blocks = match version {
+            1 | 2 => schematic,
+            3 => get_tag(schematic, "Blocks")?,
+            version => return Err(Error::UnsupportedVersion { version }),
+        };
+        let palette = match get_tag(blocks, "Palette")? {
+            Tag::Compound(palette) => palette,
+            _ => return Err(Error::MissingTag { name: "Palette".into() }),
+        };
+        let block_data_name = if version == 3 { "Data" } else { "BlockData" };
+        let block_data = match get_tag(blocks, block_data_name)? {
+            Tag::ByteArray(block_data) => block_data,
+            _ => return Err(Error::MissingTag { name: block_data_name.into() }),
+        };
+
+        // the file stores sizes as signed shorts
+        let width = get_short(schematic, "Width")? as u16 as i64;
+        let height = get_short(schematic, "Height")? as u16 as i64;
+        let length = get_short(schematic, "Length")? as u16 as i64;
+
+        let mut unmapped = Vec::new();
+        let mut palette_blocks = HashMap::new();
+        for (name, index) in palette {
+            let index = match index {
+                Tag::Int(index) => *index,
+                _ => return Err(Error::InvalidBlockData),
+            };
+
+            let block = mapping.to_block(registry, &SchematicState::parse(name))
+                .unwrap_or_else(|| {
+                    unmapped.push(name.clone());
+                    AIR
+                });
+            palette_blocks.insert(index, block);
+        }
+
+        // every index takes at least one byte, which bounds the volume before
+        // anything is allocated for it
+        let volume = width * height * length;
+        if volume > MAX_VOLUME {
+            return Err(Error::TooLarge { size: [width, length, height] });
+        }
+        if volume > block_data.len() as i64 {
+            return Err(Error::InvalidBlockData);
+        }
+
+        let mut schematic = Schematic {
+            size: [width, length, height],
+            blocks: vec![AIR; volume as usize],
+            unmapped,
+        };
+
+        let mut bytes = block_data.iter().map(|&byte| byte as u8);
+        for i in 0..volume {
+            let index = read_varint(&mut bytes).ok_or(Error::InvalidBlockData)?;
+            let block = *palette_blocks.get(&index).ok_or(Error::InvalidBlockData)?;
+
+            // Minecraft's Y is up and its Z points south
+            let (x, up, south) = (i % width, i / (width * length), i / width % length);
+            let index = schematic.index(Position::new(x, length - 1 - south, up));
+            schematic.blocks[index] = block;
+        }
+        if bytes.next().is_some() {
+            return Err(Error::InvalidBlockData);
+        }
+
+        Ok(schematic)
+    }
+
+    /// Write the schematic as a gzip compressed version 2 Sponge schematic.
+    pub fn to_bytes(&self, registry: &BlockRegistry, mapping: &BlockMapping) -> Result<Vec<u8>, Error> {
+        let (width, length, height) = (self.size[0], self.size[1], self.size[2]);
+
+        let mut palette: Vec<(String, Tag)> = Vec::new();
+        let mut palette_indices = HashMap::new();
+        let mut block_data = Vec::new();
+
+        for up in 0..height {
+            for south in 0..length {
+                for x in 0..width {
+                    let block = self.get(Position::new(x, length - 1 - south, up));
+                    let index = *palette_indices.entry(block).or_insert_with(|| {
+                        let state = mapping.to_schematic(registry, block).to_string();
+                        palette.push((state, Tag::Int(palette.len() as i32)));
+                        palette.len() as i32 - 1
+                    });
+
+                    write_varint(&mut block_data, index);
+                }
+            }
+        }
+
+        let root = Tag::Compound(vec![
+            ("Version".into(), Tag::Int(VERSION)),
+            ("DataVersion".into(), Tag::Int(DATA_VERSION)),
+            ("Width".into(), Tag::Short(width as u16 as i16)),
+            ("Height".into(), Tag::Short(height as u16 as i16)),
+            ("Length".into(), Tag::Short(length as u16 as i16)),
+            ("Offset".into(), Tag::IntArray(vec![0, 0, 0])),
+            ("PaletteMax".into(), Tag::Int(palette.len() as i32)),
+            ("Palette".into(), Tag::Compound(palette)),
+            ("BlockData".into(), Tag::ByteArray(block_data.into_iter().map(|byte| byte as i8).collect())),
+        ]);
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&nbt::write("Schematic", &root)?)?;
+        Ok(encoder.finish()?)
+    }
+}
+
+fn get_tag<'a>(compound: &'a Tag, name: &str) -> Result<&'a Tag, Error> {
+    compound.get(name).ok_or_else(|| Error::MissingTag { name: name.into() })
+}
+
+fn get_int(compound: &Tag, name: &str) -> Result<i32, Error> {
+    match get_tag(compound, name)? {
+        Tag::Int(value) => Ok(*value),
+        _ => Err(Error::MissingTag { name: name.into() }),
+    }
+}
+
+fn get_short(compound: &Tag, name: &str) -> Result<i16, Error> {
+    match get_tag(compound, name)? {
+        Tag::Short(value) => Ok(*value),
+        _ => Err(Error::MissingTag { name: name.into() }),
+    }
+}
+
+/// Read an unsigned variable length integer, seven bits per byte with the
+/// high bit set on every byte but the last.
+fn read_varint(bytes: &mut impl Iterator<Item = u8>) -> Option<i32> {
+    let mut value: u32 = 0;
+
+    for shift in (0..32).step_by(7) {
+        let byte = bytes.next()?;
+        value |= ((byte & 0x7f) as u32) << shift;
+
+        if byte & 0x80 == 0 {
+            return Some(value as i32);
+        }
+    }
+
+    None
+}
+
+fn write_varint(bytes: &mut Vec<u8>, value: i32) {
+    let mut value = value as u32;
+
+    while value >= 0x80 {
+        bytes.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schematic::{Mirror, Rotation};
+    use crate::world::block::Axis;
+
+    fn registry() -> BlockRegistry {
+        include_str!("../../assets/blocks.ron").parse().unwrap()
+    }
+
+    fn mapping(registry: &BlockRegistry) -> BlockMapping {
+        BlockMapping::from_str(include_str!("../../assets/schematic_blocks.ron"), registry).unwrap()
+    }
+
+    /// Blocks within 16 blocks of the origin, with air wherever no block was
+    /// placed. Further away the world is not available.
+    struct Blocks(HashMap<BlockPos, Block>);
+
+    impl BlockAccess for Blocks {
+        fn get_block(&self, position: BlockPos) -> Option<Block> {
+            if [position.x, position.y, position.z].iter().all(|coordinate| coordinate.abs() <= 16) {
+                Some(self.0.get(&position).cloned().unwrap_or(AIR))
+            } else {
+                None
+            }
+        }
+
+        fn set_block(&mut self, position: BlockPos, block: Block) -> bool {
+            if self.get_block(position).is_none() {
+                return false;
+            }
+
+            self.0.insert(position, block);
+            true
+        }
+    }
+
+    /// A 4 by 3 by 2 schematic with a different block in most positions,
+    /// including blocks with states and a block with no mapping.
+    fn schematic(registry: &BlockRegistry) -> Schematic {
+        let names = ["stone", "dirt", "log", "stone_slab", "wheat", "red_lamp"];
+        let blocks: Vec<Block> = names.iter().map(|name| registry.require(name).unwrap()).collect();
+
+        let mut world = Blocks(HashMap::new());
+        for i in 0..24 {
+            let (x, y, z) = (i % 4, i / 4 % 3, i / 12);
+            let block = blocks[i as usize % blocks.len()];
+            let states = (0..=7).filter(|&state| registry.get(block).is_valid_state(state)).count() as i64;
+            world.set_block(BlockPos::new(x, y, z), block.with_state((i % states) as u8));
+        }
+
+        Schematic::copy(&world, BlockPos::new(3, 2, 1), BlockPos::new(0, 0, 0)).unwrap()
+    }
+
+    fn assert_same_blocks(a: &Schematic, b: &Schematic) {
+        assert_eq!(a.size(), b.size());
+        assert_eq!(a.blocks, b.blocks);
+    }
+
+    /// Decompress a schematic written by `to_bytes`.
+    fn uncompressed(bytes: &[u8]) -> Vec<u8> {
+        let mut data = Vec::new();
+        GzDecoder::new(bytes).read_to_end(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn compressed_schematics_round_trip() {
+        let registry = registry();
+        let mapping = mapping(&registry);
+        let schematic = schematic(&registry);
+
+        let bytes = schematic.to_bytes(&registry, &mapping).unwrap();
+        let read = Schematic::from_bytes(&bytes, &registry, &mapping).unwrap();
+
+        assert_same_blocks(&read, &schematic);
+        assert!(read.unmapped.is_empty());
+    }
+
+    #[test]
+    fn uncompressed_schematics_are_read() {
+        let registry = registry();
+        let mapping = mapping(&registry);
+        let schematic = schematic(&registry);
+
+        let data = uncompressed(&schematic.to_bytes(&registry, &mapping).unwrap());
+        let read = Schematic::from_bytes(&data, &registry, &mapping).unwrap();
+
+        assert_same_blocks(&read, &schematic);
+    }
+
+    #[test]
+    fn unknown_block_states_are_read_as_air() {
+        let registry = registry();
+        let mapping = mapping(&registry);
+        let root = Tag::Compound(vec![
+            ("Version".into(), Tag::Int(2)),
+            ("Width".into(), Tag::Short(2)),
+            ("Height".into(), Tag::Short(1)),
+            ("Length".into(), Tag::Short(1)),
+            ("Palette".into(), Tag::Compound(vec![
+                ("minecraft:stone".into(), Tag::Int(0)),
+                ("minecraft:diamond_block".into(), Tag::Int(1)),
+            ])),
+            ("BlockData".into(), Tag::ByteArray(vec![0, 1])),
+        ]);
+
+        let read = Schematic::from_bytes(&nbt::write("Schematic", &root).unwrap(), &registry, &mapping).unwrap();
+
+        assert_eq!(read.get(Position::new(0, 0, 0)), registry.require("stone").unwrap());
+        assert_eq!(read.get(Position::new(1, 0, 0)), AIR);
+        assert_eq!(read.unmapped, vec!["minecraft:diamond_block".to_string()]);
+    }
+
+    #[test]
+    fn truncated_schematics_are_rejected() {
+        let registry = registry();
+        let mapping = mapping(&registry);
+        let bytes = schematic(&registry).to_bytes(&registry, &mapping).unwrap();
+        let data = uncompressed(&bytes);
+
+        for len in 0..data.len() {
+            assert!(Schematic::from_bytes(&data[..len], &registry, &mapping).is_err(), "read {} bytes", len);
+        }
+        for len in 0..bytes.len() {
+            assert!(Schematic::from_bytes(&bytes[..len], &registry, &mapping).is_err(), "read {} bytes", len);
+        }
+    }
+
+    #[test]
+    fn oversized_schematics_are_rejected() {
+        let registry = registry();
+        let mapping = mapping(&registry);
+        let root = Tag::Compound(vec![
+            ("Version".into(), Tag::Int(2)),
+            ("Width".into(), Tag::Short(-1)),
+            ("Height".into(), Tag::Short(-1)),
+            ("Length".into(), Tag::Short(-1)),
+            ("Palette".into(), Tag::Compound(vec![("minecraft:air".into(), Tag::Int(0))])),
+            ("BlockData".into(), Tag::ByteArray(vec![0])),
+        ]);
+
+        match Schematic::from_bytes(&nbt::write("Schematic", &root).unwrap(), &registry, &mapping) {
+            Err(Error::TooLarge { size }) => assert_eq!(size, [65535, 65535, 65535]),
+            result => panic!("expected a size error, got {:?}", result.err()),
+        }
+
+        let world = Blocks(HashMap::new());
+        match Schematic::copy(&world, BlockPos::new(0, 0, 0), BlockPos::new(70_000, 0, 0)) {
+            Err(Error::TooLarge { .. }) => {}
+            result => panic!("expected a size error, got {:?}", result.err()),
+        }
+    }
+
+    #[test]
+    fn copying_unavailable_blocks_fails() {
+        let world = Blocks(HashMap::new());
+
+        match Schematic::copy(&world, BlockPos::new(10, 0, 0), BlockPos::new(20, 0, 0)) {
+            Err(Error::Unavailable { position }) => assert_eq!(position, BlockPos::new(17, 0, 0)),
+            result => panic!("expected an unavailable block error, got {:?}", result.err()),
+        }
+    }
+
+    #[test]
+    fn pasting_transforms_blocks() {
+        let registry = registry();
+        let log = registry.require("log").unwrap();
+        let stone = registry.require("stone").unwrap();
+
+        // a log lying east to west with stone at its east end
+        let mut world = Blocks(HashMap::new());
+        world.set_block(BlockPos::new(0, 0, 0), log.with_state(Axis::X.to_state()));
+        world.set_block(BlockPos::new(1, 0, 0), stone);
+        let schematic = Schematic::copy(&world, BlockPos::new(0, 0, 0), BlockPos::new(1, 0, 0)).unwrap();
+
+        let transform = Transform { rotation: Rotation::Clockwise90, mirror: Mirror::X };
+        let origin = BlockPos::new(5, 5, 5);
+        assert_eq!(schematic.paste(&mut world, &registry, origin, transform), 2);
+
+        // mirrored the stone is at the west end, then turned to the north end
+        assert_eq!(world.get_block(BlockPos::new(5, 5, 5)), Some(log.with_state(Axis::Y.to_state())));
+        assert_eq!(world.get_block(BlockPos::new(5, 6, 5)), Some(stone));
+    }
+
+    #[test]
+    fn pasting_skips_unavailable_blocks() {
+        let registry = registry();
+        let schematic = schematic(&registry);
+        let mut world = Blocks(HashMap::new());
+
+        // only the lowest layer is within the available area
+        let placed = schematic.paste(&mut world, &registry, BlockPos::new(0, 0, 16), Transform::IDENTITY);
+        assert_eq!(placed, 12);
+    }
+}
@@ -0,0 +1,227 @@
+use crate::world::Position;
+use crate::world::block::{Axis, Block, BlockProperty, BlockRegistry};
+
+/// Rotation of a schematic about the vertical axis, clockwise when seen from
+/// above.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Rotation {
+    None,
+    Clockwise90,
+    Clockwise180,
+    Clockwise270,
+}
+
+impl Rotation {
+    /// Get the rotation by a number of degrees, which must be a multiple of
+    /// 90.
+    pub fn from_degrees(degrees: i64) -> Option<Rotation> {
+        match degrees.rem_euclid(360) {
+            0 => Some(Rotation::None),
+            90 => Some(Rotation::Clockwise90),
+            180 => Some(Rotation::Clockwise180),
+            270 => Some(Rotation::Clockwise270),
+            _ => None,
+        }
+    }
+
+    /// Determine if the rotation swaps the X and Y axes.
+    fn is_quarter_turn(&self) -> bool {
+        match self {
+            Rotation::Clockwise90 | Rotation::Clockwise270 => true,
+            Rotation::None | Rotation::Clockwise180 => false,
+        }
+    }
+}
+
+/// Mirroring of a schematic across a vertical plane.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Mirror {
+    None,
+    /// Flip along the X axis, swapping east and west.
+    X,
+    /// Flip along the Y axis, swapping north and south.
+    Y,
+}
+
+/// How a schematic is placed when pasted: mirrored first, then rotated, with
+/// the result occupying the same corner of the world as the original.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Transform {
+    pub rotation: Rotation,
+    pub mirror: Mirror,
+}
+
+impl Transform {
+    pub const IDENTITY: Transform = Transform {
+        rotation: Rotation::None,
+        mirror: Mirror::None,
+    };
+
+    /// Get the size of a schematic after it has been transformed.
+    pub fn size(&self, size: [i64; 3]) -> [i64; 3] {
+        if self.rotation.is_quarter_turn() {
+            [size[1], size[0], size[2]]
+        } else {
+            size
+        }
+    }
+
+    /// Move a position in a schematic of the given size to its place in the
+    /// transformed schematic.
+    pub fn position(&self, size: [i64; 3], position: Position) -> Position {
+        let (width, length) = (size[0], size[1]);
+        let (mut x, mut y) = (position.x, position.y);
+
+        match self.mirror {
+            Mirror::None => {}
+            Mirror::X => x = width - 1 - x,
+            Mirror::Y => y = length - 1 - y,
+        }
+
+        let (x, y) = match self.rotation {
+            Rotation::None => (x, y),
+            Rotation::Clockwise90 => (y, width - 1 - x),
+            Rotation::Clockwise180 => (width - 1 - x, length - 1 - y),
+            Rotation::Clockwise270 => (length - 1 - y, x),
+        };
+
+        Position::new(x, y, position.z)
+    }
+
+    /// Turn a block to match the transformed schematic: blocks lying along
+    /// the X or Y axis swap axes when given a quarter turn.
+    pub fn block(&self, registry: &BlockRegistry, block: Block) -> Block {
+        if registry.get(block).property != BlockProperty::Axis || !self.rotation.is_quarter_turn() {
+            return block;
+        }
+
+        let axis = match Axis::from_state(block.state()) {
+            Axis::X => Axis::Y,
+            Axis::Y => Axis::X,
+            Axis::Z => Axis::Z,
+        };
+        block.with_state(axis.to_state())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROTATIONS: [Rotation; 4] = [
+        Rotation::None,
+        Rotation::Clockwise90,
+        Rotation::Clockwise180,
+        Rotation::Clockwise270,
+    ];
+    const MIRRORS: [Mirror; 3] = [Mirror::None, Mirror::X, Mirror::Y];
+
+    fn registry() -> BlockRegistry {
+        include_str!("../../assets/blocks.ron").parse().unwrap()
+    }
+
+    /// Transform a position by reflecting and turning it about the origin,
+    /// then moving the transformed box back to the origin.
+    fn expected_position(transform: Transform, size: [i64; 3], position: Position) -> Position {
+        let turns = ROTATIONS.iter().position(|&rotation| rotation == transform.rotation).unwrap();
+        let apply = |(mut x, mut y): (i64, i64)| {
+            match transform.mirror {
+                Mirror::None => {}
+                Mirror::X => x = -x,
+                Mirror::Y => y = -y,
+            }
+            // a clockwise quarter turn seen from above turns east to south
+            for _ in 0..turns {
+                let (east, north) = (x, y);
+                x = north;
+                y = -east;
+            }
+            (x, y)
+        };
+
+        let corners = [(0, 0), (size[0] - 1, 0), (0, size[1] - 1), (size[0] - 1, size[1] - 1)];
+        let min_x = corners.iter().map(|&corner| apply(corner).0).min().unwrap();
+        let min_y = corners.iter().map(|&corner| apply(corner).1).min().unwrap();
+
+        let (x, y) = apply((position.x, position.y));
+        Position::new(x - min_x, y - min_y, position.z)
+    }
+
+    #[test]
+    fn positions_match_turning_and_reflecting() {
+        let size = [3, 5, 2];
+
+        for &rotation in &ROTATIONS {
+            for &mirror in &MIRRORS {
+                let transform = Transform { rotation, mirror };
+                let transformed_size = transform.size(size);
+
+                for z in 0..size[2] {
+                    for y in 0..size[1] {
+                        for x in 0..size[0] {
+                            let position = Position::new(x, y, z);
+                            let transformed = transform.position(size, position);
+
+                            assert_eq!(transformed, expected_position(transform, size, position), "{:?}", transform);
+                            assert!((0..3).all(|axis| (0..transformed_size[axis]).contains(&transformed.get(axis))));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn quarter_turns_swap_width_and_length() {
+        for &mirror in &MIRRORS {
+            let size = |rotation| Transform { rotation, mirror }.size([3, 5, 2]);
+
+            assert_eq!(size(Rotation::None), [3, 5, 2]);
+            assert_eq!(size(Rotation::Clockwise90), [5, 3, 2]);
+            assert_eq!(size(Rotation::Clockwise180), [3, 5, 2]);
+            assert_eq!(size(Rotation::Clockwise270), [5, 3, 2]);
+        }
+    }
+
+    #[test]
+    fn clockwise_turns_move_the_north_west_corner_east() {
+        let transform = Transform { rotation: Rotation::Clockwise90, mirror: Mirror::None };
+
+        // the north west corner of a 3 by 5 schematic ends up in the north
+        // east corner of the 5 by 3 result
+        assert_eq!(transform.position([3, 5, 1], Position::new(0, 4, 0)), Position::new(4, 2, 0));
+    }
+
+    #[test]
+    fn quarter_turns_swap_horizontal_log_axes() {
+        let registry = registry();
+        let log = registry.require("log").unwrap();
+        let stone = registry.require("stone").unwrap();
+        let log_along = |axis: Axis| log.with_state(axis.to_state());
+
+        for &rotation in &ROTATIONS {
+            for &mirror in &MIRRORS {
+                let transform = Transform { rotation, mirror };
+                let turn = |block| transform.block(&registry, block);
+
+                if rotation == Rotation::Clockwise90 || rotation == Rotation::Clockwise270 {
+                    assert_eq!(turn(log_along(Axis::X)), log_along(Axis::Y));
+                    assert_eq!(turn(log_along(Axis::Y)), log_along(Axis::X));
+                } else {
+                    assert_eq!(turn(log_along(Axis::X)), log_along(Axis::X));
+                    assert_eq!(turn(log_along(Axis::Y)), log_along(Axis::Y));
+                }
+                assert_eq!(turn(log_along(Axis::Z)), log_along(Axis::Z));
+                assert_eq!(turn(stone), stone);
+            }
+        }
+    }
+
+    #[test]
+    fn rotations_are_parsed_from_multiples_of_ninety_degrees() {
+        assert_eq!(Rotation::from_degrees(90), Some(Rotation::Clockwise90));
+        assert_eq!(Rotation::from_degrees(-90), Some(Rotation::Clockwise270));
+        assert_eq!(Rotation::from_degrees(360), Some(Rotation::None));
+        assert_eq!(Rotation::from_degrees(45), None);
+    }
+}
@@ -0,0 +1,261 @@
+use std::collections::HashMap;
+
+use crate::world::{BlockAccess, BlockPos, Position};
+use crate::world::block::{AIR, BlockRegistry};
+
+use super::palette::BlockColors;
+use super::vox_file::{Error, MAX_MODEL_SIZE, Voxel, VoxFile, VoxModel};
+
+/// Place the voxels of a model in the world with the corner of the model at
+/// `origin`, as the registered blocks closest in colour.
+///
+/// Empty voxels leave the world unchanged. Returns the number of blocks
+/// placed; voxels in parts of the world which are not available are skipped.
+pub fn stamp_model<A: BlockAccess>(
+    access: &mut A,
+    registry: &BlockRegistry,
+    file: &VoxFile,
+    model_index: usize,
+    origin: BlockPos,
+) -> Result<usize, Error> {
+    let model = file.models.get(model_index)
+        .ok_or(Error::MissingModel { index: model_index })?;
+
+    let colors = BlockColors::new(registry);
+    let mut blocks = HashMap::new();
+    let mut stamped = Vec::with_capacity(model.voxels.len());
+    for voxel in &model.voxels {
+        let block = match blocks.get(&voxel.color_index) {
+            Some(&block) => block,
+            None => {
+                let block = colors.nearest(file.palette[voxel.color_index as usize])
+                    .ok_or(Error::NoBlockColors)?;
+                blocks.insert(voxel.color_index, block);
+                block
+            }
+        };
+
+        let position = origin + Position::new(voxel.x as i64, voxel.y as i64, voxel.z as i64);
+        stamped.push((position, block));
+    }
+
+    Ok(access.set_blocks(stamped))
+}
+
+/// Order the corners of a box of blocks to export, inclusive, checking that
+/// it fits in a model. Returns the lowest and highest corners.
+///
+/// Use this before loading the blocks, to avoid loading a region which is
+/// too large to export.
+pub fn export_bounds(min: BlockPos, max: BlockPos) -> Result<(BlockPos, BlockPos), Error> {
+    let (min, max) = (
+        BlockPos::new(min.x.min(max.x), min.y.min(max.y), min.z.min(max.z)),
+        BlockPos::new(min.x.max(max.x), min.y.max(max.y), min.z.max(max.z)),
+    );
+
+    let size = [max.x - min.x + 1, max.y - min.y + 1, max.z - min.z + 1];
+    if size.iter().any(|&axis| axis > MAX_MODEL_SIZE as i64) {
+        return Err(Error::ModelTooLarge { size, max: MAX_MODEL_SIZE });
+    }
+
+    Ok((min, max))
+}
+
+/// Copy the blocks in the box between two corners, inclusive, into a model.
+///
+/// Each type of block becomes a colour in the palette, so block states are
+/// not kept. Air, blocks without a colour and parts of the world which are
+/// not available become empty voxels.
+pub fn export_region<A: BlockAccess>(
+    access: &A,
+    registry: &BlockRegistry,
+    min: BlockPos,
+    max: BlockPos,
+) -> Result<VoxFile, Error> {
+    let (min, max) = export_bounds(min, max)?;
+    let size = [max.x - min.x + 1, max.y - min.y + 1, max.z - min.z + 1];
+
+    let mut file = VoxFile {
+        models: Vec::new(),
+        palette: [[0; 4]; 256],
+    };
+    let mut color_indices = HashMap::new();
+    let mut voxels = Vec::new();
+
+    for x in 0..size[0] {
+        for y in 0..size[1] {
+            for z in 0..size[2] {
+                let block = match access.get_block(min + Position::new(x, y, z)) {
+                    Some(block) if block != AIR => block,
+                    _ => continue,
+                };
+                let (r, g, b) = match registry.get(block).color {
+                    Some(color) => color,
+                    None => continue,
+                };
+
+                // colour indices start at 1, since 0 is empty space
+                let next_index = color_indices.len() + 1;
+                let color_index = *color_indices.entry(block.id()).or_insert(next_index);
+                if color_index > 255 {
+                    return Err(Error::TooManyBlockTypes);
+                }
+                file.palette[color_index] = [r, g, b, 0xff];
+
+                voxels.push(Voxel { x: x as u8, y: y as u8, z: z as u8, color_index: color_index as u8 });
+            }
+        }
+    }
+
+    file.models.push(VoxModel {
+        size: [size[0] as u32, size[1] as u32, size[2] as u32],
+        voxels,
+    });
+
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vox::default_palette;
+    use crate::world::block::Block;
+
+    fn registry() -> BlockRegistry {
+        include_str!("../../assets/blocks.ron").parse().unwrap()
+    }
+
+    /// A registry of air and the given number of blocks, each with a
+    /// different colour when `colored`.
+    fn generated_registry(count: usize, colored: bool) -> BlockRegistry {
+        let mut source = String::from(r#"[(name: "air", solid: false, opaque: false),"#);
+        for i in 0..count {
+            let color = if colored { format!("Some(({}, {}, 0))", i % 256, i / 256) } else { "None".into() };
+            source.push_str(&format!(r#"(name: "block_{}", color: {}),"#, i, color));
+        }
+        source.push(']');
+
+        source.parse().unwrap()
+    }
+
+    /// Blocks within 16 blocks of the origin, with air wherever no block was
+    /// placed. Further away the world is not available.
+    struct Blocks(HashMap<BlockPos, Block>);
+
+    impl BlockAccess for Blocks {
+        fn get_block(&self, position: BlockPos) -> Option<Block> {
+            if [position.x, position.y, position.z].iter().all(|coordinate| coordinate.abs() <= 16) {
+                Some(self.0.get(&position).cloned().unwrap_or(AIR))
+            } else {
+                None
+            }
+        }
+
+        fn set_block(&mut self, position: BlockPos, block: Block) -> bool {
+            if self.get_block(position).is_none() {
+                return false;
+            }
+
+            self.0.insert(position, block);
+            true
+        }
+    }
+
+    #[test]
+    fn exported_regions_are_stamped_back() {
+        let registry = registry();
+        let names = ["stone", "dirt", "log", "glass", "red_lamp"];
+
+        let mut world = Blocks(HashMap::new());
+        for (i, name) in names.iter().enumerate() {
+            let block = registry.require(name).unwrap();
+            world.set_block(BlockPos::new(i as i64, i as i64 % 2, 1), block);
+        }
+        // states are not kept
+        world.set_block(BlockPos::new(0, 1, 0), registry.require("log").unwrap().with_state(1));
+
+        let file = export_region(&world, &registry, BlockPos::new(4, 1, 1), BlockPos::new(0, 0, 0)).unwrap();
+        assert_eq!(file.models[0].size, [5, 2, 2]);
+        assert_eq!(file.models[0].voxels.len(), names.len() + 1);
+
+        let file = VoxFile::from_bytes(&file.to_bytes()).unwrap();
+        let mut stamped = Blocks(HashMap::new());
+        let placed = stamp_model(&mut stamped, &registry, &file, 0, BlockPos::new(-8, -8, -8)).unwrap();
+        assert_eq!(placed, names.len() + 1);
+
+        for (&position, &block) in &world.0 {
+            let moved = position + Position::new(-8, -8, -8);
+            assert_eq!(stamped.get_block(moved), Some(block.with_state(0)), "{:?}", position);
+        }
+    }
+
+    #[test]
+    fn stamping_skips_unavailable_blocks() {
+        let registry = registry();
+        let file = VoxFile {
+            models: vec![VoxModel {
+                size: [2, 1, 1],
+                voxels: vec![
+                    Voxel { x: 0, y: 0, z: 0, color_index: 1 },
+                    Voxel { x: 1, y: 0, z: 0, color_index: 2 },
+                ],
+            }],
+            palette: default_palette(),
+        };
+
+        let mut world = Blocks(HashMap::new());
+        assert_eq!(stamp_model(&mut world, &registry, &file, 0, BlockPos::new(16, 0, 0)).unwrap(), 1);
+
+        match stamp_model(&mut world, &registry, &file, 1, BlockPos::new(0, 0, 0)) {
+            Err(Error::MissingModel { index: 1 }) => {}
+            result => panic!("expected a missing model error, got {:?}", result.err()),
+        }
+    }
+
+    #[test]
+    fn stamping_needs_block_colors() {
+        let registry = generated_registry(3, false);
+        let file = VoxFile {
+            models: vec![VoxModel {
+                size: [1, 1, 1],
+                voxels: vec![Voxel { x: 0, y: 0, z: 0, color_index: 1 }],
+            }],
+            palette: default_palette(),
+        };
+
+        match stamp_model(&mut Blocks(HashMap::new()), &registry, &file, 0, BlockPos::new(0, 0, 0)) {
+            Err(Error::NoBlockColors) => {}
+            result => panic!("expected a colour error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn exporting_many_types_of_block_fails() {
+        let registry = generated_registry(256, true);
+
+        // every type of block but one fits
+        let mut world = Blocks(HashMap::new());
+        for id in 1..256 {
+            world.set_block(BlockPos::new(id % 16, id / 16, 0), Block::from_raw(id as u16));
+        }
+        let file = export_region(&world, &registry, BlockPos::new(0, 0, 0), BlockPos::new(15, 15, 0)).unwrap();
+        assert_eq!(file.models[0].voxels.len(), 255);
+
+        world.set_block(BlockPos::new(0, 0, 0), Block::from_raw(256));
+        match export_region(&world, &registry, BlockPos::new(0, 0, 0), BlockPos::new(15, 15, 0)) {
+            Err(Error::TooManyBlockTypes) => {}
+            result => panic!("expected a block type error, got {:?}", result.err()),
+        }
+    }
+
+    #[test]
+    fn oversized_regions_are_not_exported() {
+        let registry = registry();
+        let world = Blocks(HashMap::new());
+
+        match export_region(&world, &registry, BlockPos::new(0, 0, 0), BlockPos::new(0, 256, 0)) {
+            Err(Error::ModelTooLarge { size, .. }) => assert_eq!(size, [1, 257, 1]),
+            result => panic!("expected a size error, got {:?}", result.err()),
+        }
+    }
+}
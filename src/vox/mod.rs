@@ -0,0 +1,10 @@
+//! Reading and writing MagicaVoxel `.vox` models, and converting them to and
+//! from blocks in the world.
+
+pub use conversion::{export_bounds, export_region, stamp_model};
+pub use palette::{BlockColors, default_palette, Rgba};
+pub use vox_file::{Error, MAX_MODEL_SIZE, Voxel, VoxFile, VoxModel};
+
+mod conversion;
+mod palette;
+mod vox_file;
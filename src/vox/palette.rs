@@ -0,0 +1,73 @@
+use crate::world::block::{Block, BlockRegistry};
+
+/// A palette colour: red, green, blue and alpha.
+pub type Rgba = [u8; 4];
+
+/// The palette MagicaVoxel uses for models saved without one.
+///
+/// Index 0 is empty space. It is followed by a cube of colours with each
+/// channel stepping down from 0xff to 0x00, without black, and then by ramps
+/// of red, green, blue and grey.
+pub fn default_palette() -> [Rgba; 256] {
+    const CUBE_STEPS: [u8; 6] = [0xff, 0xcc, 0x99, 0x66, 0x33, 0x00];
+    const RAMP_STEPS: [u8; 10] = [0xee, 0xdd, 0xbb, 0xaa, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+
+    let mut palette = [[0; 4]; 256];
+    let mut index = 1;
+
+    for &r in &CUBE_STEPS {
+        for &g in &CUBE_STEPS {
+            for &b in &CUBE_STEPS {
+                if (r, g, b) != (0, 0, 0) {
+                    palette[index] = [r, g, b, 0xff];
+                    index += 1;
+                }
+            }
+        }
+    }
+
+    for channels in &[[true, false, false], [false, true, false], [false, false, true], [true, true, true]] {
+        for &step in &RAMP_STEPS {
+            let channel = |enabled: bool| if enabled { step } else { 0 };
+            palette[index] = [channel(channels[0]), channel(channels[1]), channel(channels[2]), 0xff];
+            index += 1;
+        }
+    }
+
+    palette
+}
+
+/// The registered blocks which have a colour, for matching colours to
+/// blocks.
+pub struct BlockColors {
+    blocks: Vec<(Block, (u8, u8, u8))>,
+}
+
+impl BlockColors {
+    pub fn new(registry: &BlockRegistry) -> BlockColors {
+        BlockColors {
+            blocks: registry.iter()
+                .filter_map(|(block, block_type)| block_type.color.map(|color| (block, color)))
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// Find the block whose colour is closest to the given colour, or `None`
+    /// if no block has a colour.
+    pub fn nearest(&self, color: Rgba) -> Option<Block> {
+        let distance = |(r, g, b): (u8, u8, u8)| {
+            let dr = r as i32 - color[0] as i32;
+            let dg = g as i32 - color[1] as i32;
+            let db = b as i32 - color[2] as i32;
+            dr * dr + dg * dg + db * db
+        };
+
+        self.blocks.iter()
+            .min_by_key(|&&(_, block_color)| distance(block_color))
+            .map(|&(block, _)| block)
+    }
+}
@@ -0,0 +1,336 @@
+use std::convert::TryInto;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use super::palette::{default_palette, Rgba};
+
+const MAGIC: [u8; 4] = *b"VOX ";
+/// Version written by MagicaVoxel 0.98, which any later version can read.
+const VERSION: u32 = 150;
+/// Largest size of a model along each axis.
+pub const MAX_MODEL_SIZE: u32 = 256;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "I/O error")]
+    Io(#[cause] io::Error),
+    #[fail(display = "File is not a MagicaVoxel model")]
+    NotVox,
+    #[fail(display = "Model file ends unexpectedly")]
+    Truncated,
+    #[fail(display = "Model file has no model {}", index)]
+    MissingModel { index: usize },
+    #[fail(display = "Models can be at most {} voxels across, not {:?}", max, size)]
+    ModelTooLarge { size: [i64; 3], max: u32 },
+    #[fail(display = "No registered block has a colour")]
+    NoBlockColors,
+    #[fail(display = "Models can contain at most 255 types of block")]
+    TooManyBlockTypes,
+}
+
+impl From<io::Error> for Error {
+    fn from(other: io::Error) -> Self {
+        Error::Io(other)
+    }
+}
+
+/// A single voxel of a model, with coordinates from the corner of the model
+/// and the index of its colour in the palette.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Voxel {
+    pub x: u8,
+    pub y: u8,
+    pub z: u8,
+    pub color_index: u8,
+}
+
+/// A model: its size along each axis, and its voxels which are not empty.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VoxModel {
+    pub size: [u32; 3],
+    pub voxels: Vec<Voxel>,
+}
+
+/// The contents of a `.vox` file: one or more models sharing a palette.
+///
+/// Like the world, MagicaVoxel uses Z as the up axis. Only the models and the
+/// palette are kept; the scene graph, materials and other extensions are
+/// ignored when reading.
+#[derive(Clone, Debug)]
+pub struct VoxFile {
+    pub models: Vec<VoxModel>,
+    /// Colours by colour index. Index 0 is empty space and never used by a
+    /// voxel.
+    pub palette: [Rgba; 256],
+}
+
+impl VoxFile {
+    pub fn load(path: &Path) -> Result<VoxFile, Error> {
+        VoxFile::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        Ok(fs::write(path, self.to_bytes())?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<VoxFile, Error> {
+        let mut reader = Reader::new(bytes);
+        if reader.bytes(4).map_err(|_| Error::NotVox)? != MAGIC {
+            return Err(Error::NotVox);
+        }
+        // the layout of the chunks read here is the same in every version
+        let _version = reader.u32()?;
+
+        // every other chunk is a child of the main chunk
+        let (id, _, children) = reader.chunk()?;
+        if id != *b"MAIN" {
+            return Err(Error::NotVox);
+        }
+
+        let mut file = VoxFile {
+            models: Vec::new(),
+            palette: default_palette(),
+        };
+        let mut size = None;
+
+        let mut reader = Reader::new(children);
+        while !reader.is_empty() {
+            let (id, content, _) = reader.chunk()?;
+            let mut content = Reader::new(content);
+
+            match &id {
+                b"SIZE" => {
+                    let model_size = [content.u32()?, content.u32()?, content.u32()?];
+                    if model_size.iter().any(|&axis| axis > MAX_MODEL_SIZE) {
+                        return Err(Error::ModelTooLarge {
+                            size: [model_size[0] as i64, model_size[1] as i64, model_size[2] as i64],
+                            max: MAX_MODEL_SIZE,
+                        });
+                    }
+                    size = Some(model_size);
+                }
+                b"XYZI" => {
+                    // each model is a size followed by its voxels
+                    let size = size.take().ok_or(Error::Truncated)?;
+                    let count = content.u32()? as usize;
+                    let voxels = content.bytes(count.checked_mul(4).ok_or(Error::Truncated)?)?
+                        .chunks_exact(4)
+                        .map(|voxel| Voxel { x: voxel[0], y: voxel[1], z: voxel[2], color_index: voxel[3] })
+                        .collect();
+
+                    file.models.push(VoxModel { size, voxels });
+                }
+                b"RGBA" => {
+                    // the first colour in the chunk is colour index 1
+                    for index in 1..256 {
+                        file.palette[index] = content.bytes(4)?.try_into().unwrap();
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(file)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut children = Vec::new();
+        for model in &self.models {
+            let size: Vec<u8> = model.size.iter()
+                .flat_map(|axis| axis.to_le_bytes().to_vec())
+                .collect();
+            write_chunk(&mut children, b"SIZE", &size);
+
+            let mut voxels = (model.voxels.len() as u32).to_le_bytes().to_vec();
+            for voxel in &model.voxels {
+                voxels.extend_from_slice(&[voxel.x, voxel.y, voxel.z, voxel.color_index]);
+            }
+            write_chunk(&mut children, b"XYZI", &voxels);
+        }
+
+        let mut palette: Vec<u8> = self.palette[1..].iter().flatten().copied().collect();
+        palette.extend_from_slice(&[0; 4]);
+        write_chunk(&mut children, b"RGBA", &palette);
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(b"MAIN");
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&(children.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&children);
+        bytes
+    }
+}
+
+/// Write a chunk without children.
+fn write_chunk(bytes: &mut Vec<u8>, id: &[u8; 4], content: &[u8]) {
+    bytes.extend_from_slice(id);
+    bytes.extend_from_slice(&(content.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&0u32.to_le_bytes());
+    bytes.extend_from_slice(content);
+}
+
+/// A chunk of a `.vox` file: its id, content and children.
+type Chunk<'a> = ([u8; 4], &'a [u8], &'a [u8]);
+
+/// Reads little endian values and chunks from a byte slice, failing instead
+/// of panicking when the slice runs out.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Reader<'a> {
+        Reader { bytes }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.bytes.len() < len {
+            return Err(Error::Truncated);
+        }
+
+        let (bytes, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    /// Read a chunk, returning its id, content and children.
+    fn chunk(&mut self) -> Result<Chunk<'a>, Error> {
+        let id = self.bytes(4)?.try_into().unwrap();
+        let content_len = self.u32()? as usize;
+        let children_len = self.u32()? as usize;
+
+        Ok((id, self.bytes(content_len)?, self.bytes(children_len)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file() -> VoxFile {
+        let mut palette = default_palette();
+        palette[1] = [1, 2, 3, 4];
+        palette[255] = [250, 251, 252, 253];
+
+        VoxFile {
+            models: vec![
+                VoxModel {
+                    size: [3, 2, 1],
+                    voxels: vec![
+                        Voxel { x: 0, y: 0, z: 0, color_index: 1 },
+                        Voxel { x: 2, y: 1, z: 0, color_index: 255 },
+                    ],
+                },
+                VoxModel {
+                    size: [256, 256, 256],
+                    voxels: vec![Voxel { x: 255, y: 255, z: 255, color_index: 7 }],
+                },
+            ],
+            palette,
+        }
+    }
+
+    /// A file with a single SIZE chunk.
+    fn file_with_size(size: [u32; 3]) -> Vec<u8> {
+        let mut content = Vec::new();
+        for axis in &size {
+            content.extend_from_slice(&axis.to_le_bytes());
+        }
+        let mut children = Vec::new();
+        write_chunk(&mut children, b"SIZE", &content);
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(b"MAIN");
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&(children.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&children);
+        bytes
+    }
+
+    #[test]
+    fn files_round_trip() {
+        let file = file();
+        let read = VoxFile::from_bytes(&file.to_bytes()).unwrap();
+
+        assert_eq!(read.models, file.models);
+        assert_eq!(&read.palette[..], &file.palette[..]);
+    }
+
+    #[test]
+    fn files_without_a_palette_use_the_default() {
+        let read = VoxFile::from_bytes(&file_with_size([1, 1, 1])).unwrap();
+
+        assert!(read.models.is_empty());
+        assert_eq!(&read.palette[..], &default_palette()[..]);
+    }
+
+    #[test]
+    fn truncated_files_are_rejected() {
+        let bytes = file().to_bytes();
+
+        for len in 0..bytes.len() {
+            match VoxFile::from_bytes(&bytes[..len]) {
+                Err(Error::Truncated) | Err(Error::NotVox) => {}
+                result => panic!("read {} bytes: expected a truncation error, got {:?}", len, result.err()),
+            }
+        }
+
+        // past the header every cut is inside a chunk
+        match VoxFile::from_bytes(&bytes[..bytes.len() - 1]) {
+            Err(Error::Truncated) => {}
+            result => panic!("expected a truncation error, got {:?}", result.err()),
+        }
+    }
+
+    #[test]
+    fn other_files_are_rejected() {
+        match VoxFile::from_bytes(b"PNG and more") {
+            Err(Error::NotVox) => {}
+            result => panic!("expected a format error, got {:?}", result.err()),
+        }
+    }
+
+    #[test]
+    fn oversized_models_are_rejected() {
+        match VoxFile::from_bytes(&file_with_size([1, MAX_MODEL_SIZE + 1, 1])) {
+            Err(Error::ModelTooLarge { size, max }) => {
+                assert_eq!(size, [1, MAX_MODEL_SIZE as i64 + 1, 1]);
+                assert_eq!(max, MAX_MODEL_SIZE);
+            }
+            result => panic!("expected a size error, got {:?}", result.err()),
+        }
+
+        match VoxFile::from_bytes(&file_with_size([u32::MAX; 3])) {
+            Err(Error::ModelTooLarge { size, .. }) => assert_eq!(size, [u32::MAX as i64; 3]),
+            result => panic!("expected a size error, got {:?}", result.err()),
+        }
+    }
+
+    #[test]
+    fn voxel_counts_beyond_the_chunk_are_rejected() {
+        let mut bytes = file_with_size([1, 1, 1]);
+        let mut children = Vec::new();
+        write_chunk(&mut children, b"XYZI", &u32::MAX.to_le_bytes());
+        bytes.extend_from_slice(&children);
+        let children_len = (bytes.len() - 20) as u32;
+        bytes[16..20].copy_from_slice(&children_len.to_le_bytes());
+
+        match VoxFile::from_bytes(&bytes) {
+            Err(Error::Truncated) => {}
+            result => panic!("expected a truncation error, got {:?}", result.err()),
+        }
+    }
+}
@@ -1 +1,74 @@
-pub type Block = u8;
+/// A block in the world: the id of its type in the `BlockRegistry`, plus a
+/// small state whose meaning depends on the type's `BlockProperty`.
+///
+/// Packed into 16 bits, with the id in the low 12 bits and the state in the
+/// high 4 bits.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Block(u16);
+
+/// Air is always the first block in the registry.
+pub const AIR: Block = Block::from_id(0);
+
+impl Block {
+    pub const ID_BITS: u16 = 12;
+    pub const MAX_ID: u16 = (1 << Block::ID_BITS) - 1;
+    pub const MAX_STATE: u8 = 0xf;
+
+    /// Create a block. Panics if the id or state do not fit.
+    pub fn new(id: u16, state: u8) -> Block {
+        assert!(id <= Block::MAX_ID, "block id {} out of range", id);
+        assert!(state <= Block::MAX_STATE, "block state {} out of range", state);
+
+        Block(id | (state as u16) << Block::ID_BITS)
+    }
+
+    /// Create a block in its default state.
+    pub const fn from_id(id: u16) -> Block {
+        Block(id & Block::MAX_ID)
+    }
+
+    pub fn id(&self) -> u16 {
+        self.0 & Block::MAX_ID
+    }
+
+    pub fn state(&self) -> u8 {
+        (self.0 >> Block::ID_BITS) as u8
+    }
+
+    pub fn with_state(self, state: u8) -> Block {
+        Block::new(self.id(), state)
+    }
+
+    /// The packed representation, as stored on disk.
+    pub fn to_raw(&self) -> u16 {
+        self.0
+    }
+
+    pub fn from_raw(raw: u16) -> Block {
+        Block(raw)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_and_states_are_packed_separately() {
+        let block = Block::new(Block::MAX_ID, 5);
+        assert_eq!((block.id(), block.state()), (Block::MAX_ID, 5));
+
+        let block = block.with_state(Block::MAX_STATE);
+        assert_eq!((block.id(), block.state()), (Block::MAX_ID, Block::MAX_STATE));
+        assert_eq!(Block::from_raw(block.to_raw()), block);
+
+        assert_eq!(Block::from_id(3).with_state(0), Block::from_id(3));
+        assert_ne!(Block::from_id(3).with_state(1), Block::from_id(3));
+    }
+
+    #[test]
+    #[should_panic]
+    fn states_out_of_range_panic() {
+        Block::from_id(1).with_state(Block::MAX_STATE + 1);
+    }
+}
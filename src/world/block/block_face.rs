@@ -19,10 +19,10 @@ const NORTH: BlockFace = BlockFace {
     direction: Direction::North,
     normal: Position::new(0, 1, 0),
     vertices: [
-        Position::new(0, 1, 0),
         Position::new(1, 1, 0),
-        Position::new(1, 0, 0),
-        Position::new(0, 0, 0),
+        Position::new(0, 1, 0),
+        Position::new(0, 1, 1),
+        Position::new(1, 1, 1),
     ],
 };
 const EAST: BlockFace = BlockFace {
@@ -67,9 +67,9 @@ const BOTTOM: BlockFace = BlockFace {
     direction: Direction::Bottom,
     normal: Position::new(0, 0, -1),
     vertices: [
-        Position::new(1, 1, 0),
         Position::new(0, 1, 0),
-        Position::new(0, 1, 1),
-        Position::new(1, 1, 1),
+        Position::new(1, 1, 0),
+        Position::new(1, 0, 0),
+        Position::new(0, 0, 0),
     ],
 };
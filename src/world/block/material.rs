@@ -1,7 +0,0 @@
-use super::Block;
-
-pub const AIR: Block = 0;
-pub const STONE: Block = 1;
-pub const DIRT: Block = 2;
-pub const GRASS: Block = 3;
-pub const LOG: Block = 4;
@@ -1,6 +1,9 @@
-pub use block::Block;
+pub use block::{AIR, Block};
 pub use block_face::{BLOCK_FACES, BlockFace};
+pub use registry::{BlockRegistry, BlockTextures, BlockType, Error};
+pub use state::{Axis, BlockBounds, BlockProperty, SlabHalf};
 
 mod block;
 mod block_face;
-pub mod material;
+mod registry;
+mod state;
@@ -0,0 +1,426 @@
+use std::str::FromStr;
+
+use ron;
+use serde::Deserialize;
+
+use crate::resources::{self, Resources};
+use crate::world::Direction;
+use crate::world::light::{LightColor, MAX_LIGHT};
+
+use super::{AIR, Axis, Block, BlockBounds, BlockProperty, SlabHalf};
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Failed to load resource {}", name)]
+    ResourceLoad { name: String, #[cause] inner: resources::Error },
+    #[fail(display = "Failed to parse block registry {}", name)]
+    Parse { name: String, #[cause] inner: ron::de::Error },
+    #[fail(display = "The first block in the registry must be air, found {}", name)]
+    FirstBlockNotAir { name: String },
+    #[fail(display = "Block {} is registered more than once", name)]
+    DuplicateBlock { name: String },
+    #[fail(display = "Too many blocks registered: {}", count)]
+    TooManyBlocks { count: usize },
+    #[fail(display = "Block {} is not registered", name)]
+    UnknownBlock { name: String },
+    #[fail(display = "Block {} has a property which does not fit in its state", name)]
+    InvalidProperty { name: String },
+    #[fail(display = "Block {} emits light brighter than {}", name, max)]
+    InvalidLightEmission { name: String, max: u8 },
+}
+
+/// Atlas indices of the textures on each face of a block.
+#[derive(Clone, Debug, Deserialize)]
+pub enum BlockTextures {
+    /// The same texture on every face.
+    All(u32),
+    /// One texture for the top, one for the bottom and one for the four sides.
+    Sides { top: u32, bottom: u32, side: u32 },
+    /// A separate texture for every face.
+    Faces { top: u32, bottom: u32, north: u32, east: u32, south: u32, west: u32 },
+}
+
+impl BlockTextures {
+    pub fn get(&self, direction: Direction) -> u32 {
+        match *self {
+            BlockTextures::All(index) => index,
+            BlockTextures::Sides { top, bottom, side } => match direction {
+                Direction::Top => top,
+                Direction::Bottom => bottom,
+                _ => side,
+            },
+            BlockTextures::Faces { top, bottom, north, east, south, west } => match direction {
+                Direction::Top => top,
+                Direction::Bottom => bottom,
+                Direction::North => north,
+                Direction::East => east,
+                Direction::South => south,
+                Direction::West => west,
+            },
+        }
+    }
+}
+
+/// Properties shared by every block of one type.
+#[derive(Clone, Debug, Deserialize)]
+pub struct BlockType {
+    pub name: String,
+    /// Textures of the block, or `None` if it is not drawn at all.
+    #[serde(default)]
+    pub textures: Option<BlockTextures>,
+    /// Solid blocks can be collided with and targeted.
+    #[serde(default = "default_true")]
+    pub solid: bool,
+    /// Opaque blocks hide the faces of their neighbours and block light.
+    /// Other visible blocks are transparent, like glass.
+    #[serde(default = "default_true")]
+    pub opaque: bool,
+    /// Colour of the block light emitted by the block, with each channel from
+    /// 0 up to `MAX_LIGHT`.
+    #[serde(default)]
+    pub light_emission: LightColor,
+    /// What the state of the block describes.
+    #[serde(default)]
+    pub property: BlockProperty,
+    /// Representative colour of the block, used where it is drawn as a
+    /// single colour such as in voxel models, or `None` if it has none.
+    #[serde(default)]
+    pub color: Option<(u8, u8, u8)>,
+}
+
+impl BlockType {
+    /// Get the atlas index of the texture on a face of the block, or `None`
+    /// if it is not drawn. Blocks oriented along an axis show their top and
+    /// bottom textures on the faces at the ends of the axis, and blocks with
+    /// a level use the tile that many places after their texture.
+    pub fn texture(&self, direction: Direction, state: u8) -> Option<u32> {
+        let textures = self.textures.as_ref()?;
+
+        Some(match self.property {
+            BlockProperty::Axis => textures.get(Axis::from_state(state).unrotate(direction)),
+            BlockProperty::Level(_) => textures.get(direction) + state as u32,
+            _ => textures.get(direction),
+        })
+    }
+
+    /// Get the box occupied by the block in the given state.
+    pub fn bounds(&self, state: u8) -> BlockBounds {
+        let mut bounds = BlockBounds::full();
+
+        if let BlockProperty::Slab = self.property {
+            match SlabHalf::from_state(state) {
+                SlabHalf::Bottom => bounds.max.z = 0.5,
+                SlabHalf::Top => bounds.min.z = 0.5,
+                SlabHalf::Double => {}
+            }
+        }
+
+        bounds
+    }
+
+    /// Determine if the block fills its cell completely and can not be seen
+    /// through, hiding the faces of its neighbours and blocking light.
+    pub fn occludes(&self, state: u8) -> bool {
+        self.opaque && self.bounds(state).is_full()
+    }
+
+    /// Get the state of a block placed against the `face` of another block,
+    /// where `height` is how far up the face it was placed, from 0 to 1.
+    ///
+    /// Blocks with an axis point away from the face, and slabs fill the half
+    /// of the cell nearest to where they were placed.
+    pub fn placement_state(&self, face: Direction, height: f32) -> u8 {
+        match self.property {
+            BlockProperty::Axis => Axis::of_direction(face).to_state(),
+            BlockProperty::Slab => {
+                let half = match face {
+                    Direction::Top => SlabHalf::Bottom,
+                    Direction::Bottom => SlabHalf::Top,
+                    _ if height > 0.5 => SlabHalf::Top,
+                    _ => SlabHalf::Bottom,
+                };
+                half.to_state()
+            }
+            BlockProperty::None | BlockProperty::Level(_) => 0,
+        }
+    }
+
+    /// Determine if a state is valid for this type of block.
+    pub fn is_valid_state(&self, state: u8) -> bool {
+        match self.property {
+            BlockProperty::None => state == 0,
+            BlockProperty::Axis | BlockProperty::Slab => state <= 2,
+            BlockProperty::Level(max) => state <= max,
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// The types of block which can exist in the world, indexed by `Block`.
+pub struct BlockRegistry {
+    blocks: Vec<BlockType>,
+}
+
+impl BlockRegistry {
+    /// Load the block types from a RON resource containing a list of
+    /// `BlockType`s. The position of each block type in the list is its
+    /// `Block` id.
+    pub fn from_res(res: &Resources, name: &str) -> Result<BlockRegistry, Error> {
+        let source = res.load_string(name)
+            .map_err(|e| Error::ResourceLoad { name: name.into(), inner: e })?;
+
+        source.parse::<BlockRegistry>()
+            .map_err(|e| match e {
+                Error::Parse { inner, .. } => Error::Parse { name: name.into(), inner },
+                e => e,
+            })
+    }
+
+    pub fn from_types(blocks: Vec<BlockType>) -> Result<BlockRegistry, Error> {
+        match blocks.get(AIR.id() as usize) {
+            Some(air) if air.name == "air" => {}
+            Some(other) => return Err(Error::FirstBlockNotAir { name: other.name.clone() }),
+            None => return Err(Error::FirstBlockNotAir { name: String::new() }),
+        }
+
+        if blocks.len() > Block::MAX_ID as usize + 1 {
+            return Err(Error::TooManyBlocks { count: blocks.len() });
+        }
+
+        for (i, block_type) in blocks.iter().enumerate() {
+            if blocks[..i].iter().any(|other| other.name == block_type.name) {
+                return Err(Error::DuplicateBlock { name: block_type.name.clone() });
+            }
+
+            let LightColor(red, green, blue) = block_type.light_emission;
+            if red.max(green).max(blue) > MAX_LIGHT {
+                return Err(Error::InvalidLightEmission { name: block_type.name.clone(), max: MAX_LIGHT });
+            }
+
+            if let BlockProperty::Level(max) = block_type.property {
+                if max > Block::MAX_STATE {
+                    return Err(Error::InvalidProperty { name: block_type.name.clone() });
+                }
+            }
+        }
+
+        Ok(BlockRegistry {
+            blocks,
+        })
+    }
+
+    /// Get the type of a block. Unregistered blocks are treated as air.
+    pub fn get(&self, block: Block) -> &BlockType {
+        self.blocks.get(block.id() as usize)
+            .unwrap_or(&self.blocks[AIR.id() as usize])
+    }
+
+    /// Determine if a block hides the faces of its neighbours and blocks light.
+    pub fn occludes(&self, block: Block) -> bool {
+        self.get(block).occludes(block.state())
+    }
+
+    /// Find a block by name.
+    pub fn id(&self, name: &str) -> Option<Block> {
+        self.blocks.iter()
+            .position(|block_type| block_type.name == name)
+            .map(|index| Block::from_id(index as u16))
+    }
+
+    /// Find a block by name, failing if it is not registered.
+    pub fn require(&self, name: &str) -> Result<Block, Error> {
+        self.id(name)
+            .ok_or_else(|| Error::UnknownBlock { name: name.into() })
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Determine if no block types are registered, which is never the case
+    /// since every registry contains air.
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Block, &BlockType)> {
+        self.blocks.iter()
+            .enumerate()
+            .map(|(index, block_type)| (Block::from_id(index as u16), block_type))
+    }
+}
+
+/// Parse the block types from RON source containing a list of `BlockType`s.
+impl FromStr for BlockRegistry {
+    type Err = Error;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        let blocks: Vec<BlockType> = ron::de::from_str(source)
+            .map_err(|e| Error::Parse { name: String::new(), inner: e })?;
+
+        BlockRegistry::from_types(blocks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry() -> BlockRegistry {
+        include_str!("../../../assets/blocks.ron").parse().unwrap()
+    }
+
+    #[test]
+    fn blocks_are_registered_in_order() {
+        let registry = registry();
+
+        assert_eq!(registry.id("air"), Some(AIR));
+        assert_eq!(registry.get(AIR).name, "air");
+        assert!(!registry.get(AIR).solid);
+
+        for (block, block_type) in registry.iter() {
+            assert_eq!(registry.id(&block_type.name), Some(block));
+        }
+        assert_eq!(registry.iter().count(), registry.len());
+    }
+
+    #[test]
+    fn block_fields_are_parsed() {
+        let registry: BlockRegistry = r#"[
+            (name: "air", solid: false, opaque: false),
+            (name: "stone", textures: Some(All(1))),
+            (name: "glass", textures: Some(Sides(top: 2, bottom: 3, side: 4)), opaque: false),
+        ]"#.parse().unwrap();
+
+        let stone = registry.get(registry.require("stone").unwrap());
+        assert!(stone.solid && stone.opaque);
+        assert_eq!(stone.textures.as_ref().map(|textures| textures.get(Direction::North)), Some(1));
+
+        let glass = registry.get(registry.require("glass").unwrap());
+        assert!(glass.solid && !glass.opaque);
+        let textures = glass.textures.as_ref().unwrap();
+        assert_eq!(
+            [textures.get(Direction::Top), textures.get(Direction::Bottom), textures.get(Direction::East)],
+            [2, 3, 4],
+        );
+    }
+
+    #[test]
+    fn unknown_blocks_are_air() {
+        let registry = registry();
+        let unknown = Block::from_id(registry.len() as u16);
+
+        assert_eq!(registry.get(unknown).name, "air");
+        assert_eq!(registry.id("marble"), None);
+        match registry.require("marble") {
+            Err(Error::UnknownBlock { name }) => assert_eq!(name, "marble"),
+            result => panic!("expected an unknown block error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn invalid_registries_are_rejected() {
+        match r#"[(name: "air"), (name: "stone"), (name: "stone")]"#.parse::<BlockRegistry>() {
+            Err(Error::DuplicateBlock { name }) => assert_eq!(name, "stone"),
+            result => panic!("expected a duplicate block error, got {:?}", result.err()),
+        }
+        match r#"[(name: "stone"), (name: "air")]"#.parse::<BlockRegistry>() {
+            Err(Error::FirstBlockNotAir { name }) => assert_eq!(name, "stone"),
+            result => panic!("expected a first block error, got {:?}", result.err()),
+        }
+        match "[]".parse::<BlockRegistry>() {
+            Err(Error::FirstBlockNotAir { .. }) => {}
+            result => panic!("expected a first block error, got {:?}", result.err()),
+        }
+        match r#"[(solid: false)]"#.parse::<BlockRegistry>() {
+            Err(Error::Parse { .. }) => {}
+            result => panic!("expected a parse error, got {:?}", result.err()),
+        }
+    }
+
+    #[test]
+    fn axis_blocks_turn_their_textures() {
+        let registry = registry();
+        let log = registry.get(registry.require("log").unwrap());
+        let (rings, bark) = (21, 20);
+        let texture = |axis: Axis, direction| log.texture(direction, axis.to_state()).unwrap();
+
+        assert_eq!(texture(Axis::Z, Direction::Top), rings);
+        assert_eq!(texture(Axis::Z, Direction::East), bark);
+
+        // lying east to west, the bark is on top and the rings at the ends
+        assert_eq!(texture(Axis::X, Direction::Top), bark);
+        assert_eq!(texture(Axis::X, Direction::Bottom), bark);
+        assert_eq!(texture(Axis::X, Direction::East), rings);
+        assert_eq!(texture(Axis::X, Direction::West), rings);
+        assert_eq!(texture(Axis::X, Direction::North), bark);
+
+        assert_eq!(texture(Axis::Y, Direction::Top), bark);
+        assert_eq!(texture(Axis::Y, Direction::North), rings);
+        assert_eq!(texture(Axis::Y, Direction::South), rings);
+        assert_eq!(texture(Axis::Y, Direction::East), bark);
+    }
+
+    #[test]
+    fn levels_use_the_following_tiles() {
+        let registry = registry();
+        let wheat = registry.get(registry.require("wheat").unwrap());
+
+        assert_eq!(wheat.texture(Direction::North, 0), Some(88));
+        assert_eq!(wheat.texture(Direction::North, 7), Some(95));
+        assert!(wheat.is_valid_state(7));
+        assert!(!wheat.is_valid_state(8));
+        assert_eq!(registry.get(AIR).texture(Direction::Top, 0), None);
+    }
+
+    #[test]
+    fn slabs_fill_half_of_the_cell() {
+        let registry = registry();
+        let slab = registry.require("stone_slab").unwrap();
+        let slab_type = registry.get(slab);
+        let bounds = |half: SlabHalf| slab_type.bounds(half.to_state());
+
+        assert_eq!((bounds(SlabHalf::Bottom).min.z, bounds(SlabHalf::Bottom).max.z), (0.0, 0.5));
+        assert_eq!((bounds(SlabHalf::Top).min.z, bounds(SlabHalf::Top).max.z), (0.5, 1.0));
+        assert!(bounds(SlabHalf::Double).is_full());
+
+        assert!(!registry.occludes(slab.with_state(SlabHalf::Bottom.to_state())));
+        assert!(!registry.occludes(slab.with_state(SlabHalf::Top.to_state())));
+        assert!(registry.occludes(slab.with_state(SlabHalf::Double.to_state())));
+        assert!(!slab_type.is_valid_state(3));
+
+        // glass fills the cell but can be seen through
+        assert!(!registry.occludes(registry.require("glass").unwrap()));
+        assert!(registry.occludes(registry.require("stone").unwrap()));
+    }
+
+    #[test]
+    fn placed_blocks_face_away_from_the_face() {
+        let registry = registry();
+        let log = registry.get(registry.require("log").unwrap());
+        let slab = registry.get(registry.require("stone_slab").unwrap());
+        let stone = registry.get(registry.require("stone").unwrap());
+
+        assert_eq!(log.placement_state(Direction::East, 0.5), Axis::X.to_state());
+        assert_eq!(log.placement_state(Direction::South, 0.5), Axis::Y.to_state());
+        assert_eq!(log.placement_state(Direction::Top, 0.5), Axis::Z.to_state());
+
+        assert_eq!(slab.placement_state(Direction::Top, 0.9), SlabHalf::Bottom.to_state());
+        assert_eq!(slab.placement_state(Direction::Bottom, 0.1), SlabHalf::Top.to_state());
+        assert_eq!(slab.placement_state(Direction::North, 0.75), SlabHalf::Top.to_state());
+        assert_eq!(slab.placement_state(Direction::North, 0.25), SlabHalf::Bottom.to_state());
+
+        assert_eq!(stone.placement_state(Direction::East, 0.5), 0);
+    }
+
+    #[test]
+    fn levels_must_fit_in_the_state() {
+        match r#"[(name: "air"), (name: "vine", property: Level(16))]"#.parse::<BlockRegistry>() {
+            Err(Error::InvalidProperty { name }) => assert_eq!(name, "vine"),
+            result => panic!("expected a property error, got {:?}", result.err()),
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use nalgebra as na;
+use serde::Deserialize;
+
+use crate::world::Direction;
+
+/// The kind of state a block type stores alongside its id.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+pub enum BlockProperty {
+    /// The state is unused.
+    #[default]
+    None,
+    /// The block is oriented along an `Axis`, like a log.
+    Axis,
+    /// The block is a slab filling one half of the cell, or both.
+    Slab,
+    /// The state is a level from 0 up to the given maximum, like a growth
+    /// stage or water level.
+    Level(u8),
+}
+
+/// Orientation of a block with the `Axis` property.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+impl Axis {
+    /// Vertical (Z) is the default state.
+    pub fn from_state(state: u8) -> Axis {
+        match state {
+            1 => Axis::X,
+            2 => Axis::Y,
+            _ => Axis::Z,
+        }
+    }
+
+    pub fn to_state(&self) -> u8 {
+        match self {
+            Axis::Z => 0,
+            Axis::X => 1,
+            Axis::Y => 2,
+        }
+    }
+
+    /// Axis which the normal of a face is parallel to.
+    pub fn of_direction(direction: Direction) -> Axis {
+        match direction {
+            Direction::East | Direction::West => Axis::X,
+            Direction::North | Direction::South => Axis::Y,
+            Direction::Top | Direction::Bottom => Axis::Z,
+        }
+    }
+
+    /// Rotate a face of a block oriented along this axis back into the
+    /// orientation of an upright block, so that the faces at the ends of the
+    /// axis map to `Top` and `Bottom`.
+    pub fn unrotate(&self, direction: Direction) -> Direction {
+        match (self, direction) {
+            (Axis::Z, direction) => direction,
+            (Axis::X, Direction::East) => Direction::Top,
+            (Axis::X, Direction::West) => Direction::Bottom,
+            (Axis::X, Direction::Top) => Direction::West,
+            (Axis::X, Direction::Bottom) => Direction::East,
+            (Axis::Y, Direction::North) => Direction::Top,
+            (Axis::Y, Direction::South) => Direction::Bottom,
+            (Axis::Y, Direction::Top) => Direction::South,
+            (Axis::Y, Direction::Bottom) => Direction::North,
+            (_, direction) => direction,
+        }
+    }
+}
+
+/// Which part of the cell a block with the `Slab` property fills.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SlabHalf {
+    Bottom,
+    Top,
+    Double,
+}
+
+impl SlabHalf {
+    /// The bottom half is the default state.
+    pub fn from_state(state: u8) -> SlabHalf {
+        match state {
+            1 => SlabHalf::Top,
+            2 => SlabHalf::Double,
+            _ => SlabHalf::Bottom,
+        }
+    }
+
+    pub fn to_state(&self) -> u8 {
+        match self {
+            SlabHalf::Bottom => 0,
+            SlabHalf::Top => 1,
+            SlabHalf::Double => 2,
+        }
+    }
+}
+
+/// The box occupied by a block, relative to the lowest corner of its cell.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BlockBounds {
+    pub min: na::Point3<f32>,
+    pub max: na::Point3<f32>,
+}
+
+impl BlockBounds {
+    pub fn full() -> BlockBounds {
+        BlockBounds {
+            min: na::Point3::new(0.0, 0.0, 0.0),
+            max: na::Point3::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        *self == BlockBounds::full()
+    }
+
+    /// Determine if the face of the bounds in the given direction lies on the
+    /// boundary of the cell, where it could be hidden by a neighbour.
+    pub fn touches(&self, direction: Direction) -> bool {
+        match direction {
+            Direction::East => self.max.x >= 1.0,
+            Direction::West => self.min.x <= 0.0,
+            Direction::North => self.max.y >= 1.0,
+            Direction::South => self.min.y <= 0.0,
+            Direction::Top => self.max.z >= 1.0,
+            Direction::Bottom => self.min.z <= 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::world::block::Block;
+
+    const DIRECTIONS: [Direction; 6] = [
+        Direction::North,
+        Direction::East,
+        Direction::South,
+        Direction::West,
+        Direction::Top,
+        Direction::Bottom,
+    ];
+
+    #[test]
+    fn axes_round_trip_through_states() {
+        for &axis in &[Axis::X, Axis::Y, Axis::Z] {
+            assert_eq!(Axis::from_state(axis.to_state()), axis);
+        }
+        assert_eq!(Axis::Z.to_state(), 0);
+        assert_eq!(Axis::from_state(Block::MAX_STATE), Axis::Z);
+    }
+
+    #[test]
+    fn slab_halves_round_trip_through_states() {
+        for &half in &[SlabHalf::Bottom, SlabHalf::Top, SlabHalf::Double] {
+            assert_eq!(SlabHalf::from_state(half.to_state()), half);
+        }
+        assert_eq!(SlabHalf::Bottom.to_state(), 0);
+        assert_eq!(SlabHalf::from_state(Block::MAX_STATE), SlabHalf::Bottom);
+    }
+
+    #[test]
+    fn unrotating_moves_the_ends_of_the_axis_to_the_top_and_bottom() {
+        for &axis in &[Axis::X, Axis::Y, Axis::Z] {
+            let ends: Vec<Direction> = DIRECTIONS.iter()
+                .copied()
+                .filter(|&direction| Axis::of_direction(direction) == axis)
+                .collect();
+
+            for &direction in &DIRECTIONS {
+                let unrotated = axis.unrotate(direction);
+                let is_end = unrotated == Direction::Top || unrotated == Direction::Bottom;
+                assert_eq!(is_end, ends.contains(&direction), "{:?} {:?}", axis, direction);
+            }
+
+            // every face is still a different face
+            let unrotated: HashSet<Direction> = DIRECTIONS.iter().map(|&direction| axis.unrotate(direction)).collect();
+            assert_eq!(unrotated.len(), DIRECTIONS.len());
+        }
+    }
+
+    #[test]
+    fn slab_bounds_touch_only_their_half() {
+        let mut bottom = BlockBounds::full();
+        bottom.max.z = 0.5;
+
+        assert!(BlockBounds::full().is_full());
+        assert!(!bottom.is_full());
+        assert!(bottom.touches(Direction::Bottom));
+        assert!(!bottom.touches(Direction::Top));
+        assert!(bottom.touches(Direction::East));
+    }
+}
@@ -0,0 +1,28 @@
+use crate::world::BlockPos;
+use crate::world::block::Block;
+
+/// Read and write access to the blocks of a world, whether it is loaded in
+/// the game or edited on disk.
+pub trait BlockAccess {
+    /// Get the block at a world position, or `None` if it is not available.
+    fn get_block(&self, position: BlockPos) -> Option<Block>;
+
+    /// Set the block at a world position. Returns `false` if the position is
+    /// not available.
+    fn set_block(&mut self, position: BlockPos, block: Block) -> bool;
+
+    /// Set many blocks at once, skipping positions which are not available.
+    /// Returns the number of blocks set.
+    ///
+    /// Implementations which do work after every change, such as relighting,
+    /// can override this to do it once for the whole edit.
+    fn set_blocks<I>(&mut self, blocks: I) -> usize
+    where
+        I: IntoIterator<Item = (BlockPos, Block)>,
+        Self: Sized,
+    {
+        blocks.into_iter()
+            .filter(|&(position, block)| self.set_block(position, block))
+            .count()
+    }
+}
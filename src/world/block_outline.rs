@@ -0,0 +1,101 @@
+use nalgebra as na;
+
+use crate::render_gl::{buffer, data, Program};
+use crate::resources::Resources;
+
+/// Gap between the outline and the block, so that the lines are not hidden
+/// by the faces of the block.
+const OUTLINE_GAP: f32 = 0.002;
+
+#[derive(VertexAttribPointers, Copy, Clone, Debug)]
+#[repr(C, packed)]
+struct Vertex {
+    #[location = 0]
+    pos: data::f32_f32_f32,
+}
+
+/// Draws the edges of a box in wireframe, to highlight the targeted block.
+pub struct BlockOutline {
+    program: Program,
+    vao: buffer::VertexArray,
+    _vbo: buffer::ArrayBuffer,
+    vertex_count: i32,
+    view_location: i32,
+    projection_location: i32,
+    min_location: i32,
+    max_location: i32,
+}
+
+impl BlockOutline {
+    pub fn new(res: &Resources, gl: &gl::Gl) -> Result<BlockOutline, failure::Error> {
+        let program = Program::from_res("shaders/outline", gl, res)?;
+        let view_location = program.get_uniform_location("View")?;
+        let projection_location = program.get_uniform_location("Projection")?;
+        let min_location = program.get_uniform_location("Min")?;
+        let max_location = program.get_uniform_location("Max")?;
+
+        // each of the 12 edges of a unit cube joins two corners which differ
+        // along one axis
+        let mut vertices = Vec::new();
+        for axis in 0..3 {
+            for corner in 0..4 {
+                let mut start = [0.0; 3];
+                start[(axis + 1) % 3] = (corner & 1) as f32;
+                start[(axis + 2) % 3] = (corner >> 1) as f32;
+
+                let mut end = start;
+                end[axis] = 1.0;
+
+                vertices.push(Vertex { pos: (start[0], start[1], start[2]).into() });
+                vertices.push(Vertex { pos: (end[0], end[1], end[2]).into() });
+            }
+        }
+
+        let vbo = buffer::ArrayBuffer::new(gl);
+        vbo.bind();
+        vbo.static_draw_data(&vertices);
+        vbo.unbind();
+
+        let vao = buffer::VertexArray::new(gl);
+        vao.bind();
+        vbo.bind();
+        Vertex::vertex_attrib_pointers(gl);
+        vbo.unbind();
+        vao.unbind();
+
+        Ok(BlockOutline {
+            program,
+            vao,
+            _vbo: vbo,
+            vertex_count: vertices.len() as i32,
+            view_location,
+            projection_location,
+            min_location,
+            max_location,
+        })
+    }
+
+    /// Outline the box between two corners in world space.
+    pub fn draw(
+        &self,
+        gl: &gl::Gl,
+        view_matrix: &na::Matrix4<f32>,
+        projection_matrix: &na::Matrix4<f32>,
+        min: &na::Point3<f32>,
+        max: &na::Point3<f32>,
+    ) {
+        let gap = na::Vector3::repeat(OUTLINE_GAP);
+
+        self.program.set_used();
+        self.program.set_uniform_matrix4fv(self.view_location, view_matrix);
+        self.program.set_uniform_matrix4fv(self.projection_location, projection_matrix);
+        self.program.set_uniform_3f(self.min_location, &(min.coords - gap));
+        self.program.set_uniform_3f(self.max_location, &(max.coords + gap));
+
+        self.vao.bind();
+        unsafe {
+            gl.DrawArrays(gl::LINES, 0, self.vertex_count);
+        }
+        self.vao.unbind();
+    }
+}
@@ -1,110 +1,134 @@
-use std::ops::{Index, IndexMut};
-
-use crate::render_gl::Texture;
-use crate::world::{CHUNK_SIZE, CHUNK_VOLUME, Direction, Position};
-use crate::world::block::{self, Block, BLOCK_FACES, BlockFace};
-use crate::world::light::{self, LightLevel};
+use crate::world::{ChunkPos, LocalPos};
+use crate::world::block::Block;
+use crate::world::light::LightLevel;
 
+use super::chunk_data::ChunkData;
 use super::chunk_mesh::ChunkMesh;
+use super::chunk_mesh_builder::ChunkMeshBuilder;
 
 pub struct Chunk {
-    pub position: Position,
+    pub position: ChunkPos,
     block_data: ChunkData<Block>,
     light_data: ChunkData<LightLevel>,
-    mesh: ChunkMesh,
+    mesh: Option<ChunkMesh>,
     mesh_invalidated: bool,
+    /// Revision of the latest rebuild of the mesh, so that meshes built from
+    /// outdated block data can be discarded.
+    mesh_revision: u64,
+    /// Whether blocks have changed since the chunk was loaded or last saved.
+    modified: bool,
 }
 
-struct ChunkData<T> {
-    data: [T; CHUNK_VOLUME as usize],
-}
-
-impl<T: Copy> ChunkData<T> {
-    pub fn new(default: T) -> ChunkData<T> {
-        ChunkData {
-            data: [default; CHUNK_VOLUME as usize],
+impl Chunk {
+    /// Create a chunk from already generated block and light data.
+    ///
+    /// The chunk has no mesh until one is uploaded with `upload_mesh`, and is
+    /// initially marked as needing one.
+    pub fn new(
+        position: ChunkPos,
+        block_data: ChunkData<Block>,
+        light_data: ChunkData<LightLevel>,
+    ) -> Chunk {
+        Chunk {
+            position,
+            block_data,
+            light_data,
+            mesh: None,
+            mesh_invalidated: true,
+            mesh_revision: 0,
+            modified: false,
         }
     }
-}
 
-impl<T> Index<Position> for ChunkData<T> {
-    type Output = T;
+    pub fn block_data(&self) -> &ChunkData<Block> {
+        &self.block_data
+    }
 
-    fn index(&self, index: Position) -> &Self::Output {
-        unsafe {
-            self.data.get_unchecked(i64::from(&index) as usize)
-        }
+    pub fn light_data(&self) -> &ChunkData<LightLevel> {
+        &self.light_data
     }
-}
 
-impl<T> IndexMut<Position> for ChunkData<T> {
-    fn index_mut(&mut self, index: Position) -> &mut Self::Output {
-        unsafe {
-            self.data.get_unchecked_mut(i64::from(&index) as usize)
-        }
+    /// Approximate number of bytes used by the block and light data.
+    pub fn memory_usage(&self) -> usize {
+        self.block_data.memory_usage() + self.light_data.memory_usage()
     }
-}
 
-impl Chunk {
-    pub fn new(position: Position, gl: &gl::Gl, texture: &Texture) -> Result<Chunk, failure::Error> {
-        let mut chunk = Chunk {
-            position,
-            block_data: ChunkData::new(block::material::STONE),
-            light_data: ChunkData::new(16),
-            mesh: ChunkMesh::new(gl, texture),
-            mesh_invalidated: true,
-        };
-
-        Chunk::generate_blocks(&mut chunk.block_data);
-        Chunk::calculate_lighting(&chunk.block_data, &mut chunk.light_data);
-
-        Ok(chunk)
-    }
-
-    fn generate_blocks(block_data: &mut ChunkData<Block>) {
-        for z in 0..CHUNK_SIZE {
-            for x in 0..CHUNK_SIZE {
-                for y in 0..CHUNK_SIZE {
-                    let block_position = Position::new(x, y, z);
-                    let block = match z {
-                        0 ..= 11 => block::material::STONE,
-                        12 ..= 14 => block::material::DIRT,
-                        _ => block::material::GRASS
-                    };
-                    block_data[block_position] = block;
-                }
-            }
-        }
+    /// Get the block at a position in this chunk.
+    pub fn get_block(&self, position: LocalPos) -> Block {
+        self.block_data[position]
     }
 
-    fn calculate_lighting(block_data: &ChunkData<Block>, light_data: &mut ChunkData<LightLevel>) {
-        for x in 0..CHUNK_SIZE {
-            for y in 0..CHUNK_SIZE {
-                let mut light_level: LightLevel = light::SUNLIGHT;
+    /// Replace the block at a position in this chunk and invalidate the mesh.
+    /// The lighting is left to the light engine.
+    pub fn set_block(&mut self, position: LocalPos, block: Block) {
+        self.block_data.set(position, block);
+        self.mesh_invalidated = true;
+        self.modified = true;
+    }
 
-                for z in (0..CHUNK_SIZE).rev() {
-                    let block_position = Position::new(x, y, z);
-                    let block: Block = block_data[block_position];
+    /// Determine if the chunk has unsaved changes.
+    pub fn is_modified(&self) -> bool {
+        self.modified
+    }
 
-                    light_data[block_position] = light_level;
+    pub fn mark_saved(&mut self) {
+        self.modified = false;
+    }
 
-                    if block != block::material::AIR && light_level > 1 {
-                        light_level -= 1;
-                    }
-                }
-            }
-        }
+    pub fn get_light(&self, position: LocalPos) -> LightLevel {
+        self.light_data[position]
+    }
+
+    /// Replace the light at a position in this chunk. The mesh is not
+    /// invalidated.
+    pub fn set_light(&mut self, position: LocalPos, light: LightLevel) {
+        self.light_data.set(position, light);
+    }
+
+    /// Replace all of the light in this chunk, for example after lighting it
+    /// again from scratch. The mesh is not invalidated.
+    pub fn set_light_data(&mut self, light_data: ChunkData<LightLevel>) {
+        self.light_data = light_data;
+    }
+
+    /// Rebuild the mesh on the next update, for example because a neighbouring
+    /// chunk has changed.
+    pub fn invalidate_mesh(&mut self) {
+        self.mesh_invalidated = true;
+    }
+
+    pub fn needs_remesh(&self) -> bool {
+        self.mesh_invalidated
+    }
+
+    /// Mark the mesh as being rebuilt with the given revision, which the
+    /// rebuilt mesh should be uploaded with.
+    ///
+    /// Revisions must never be reused, even by a chunk loaded again at the
+    /// same position, so a mesh built before the chunk was unloaded can not be
+    /// mistaken for a current one.
+    pub fn start_remesh(&mut self, revision: u64) {
+        self.mesh_invalidated = false;
+        self.mesh_revision = revision;
     }
 
-    pub fn update(&mut self, gl: &gl::Gl) {
-        if self.mesh_invalidated {
-            self.mesh.update(&self.block_data, &self.light_data);
-            self.mesh.flush(gl);
-            self.mesh_invalidated = false;
+    /// Upload a rebuilt mesh, unless the chunk has changed again since the
+    /// rebuild was started.
+    pub fn finish_remesh(&mut self, gl: &gl::Gl, revision: u64, builder: &ChunkMeshBuilder) {
+        if revision == self.mesh_revision {
+            self.upload_mesh(gl, builder);
         }
     }
 
+    pub fn upload_mesh(&mut self, gl: &gl::Gl, builder: &ChunkMeshBuilder) {
+        self.mesh
+            .get_or_insert_with(|| ChunkMesh::new(gl))
+            .upload(gl, builder);
+    }
+
     pub fn draw(&self, gl: &gl::Gl) {
-        self.mesh.draw(gl);
+        if let Some(mesh) = &self.mesh {
+            mesh.draw(gl);
+        }
     }
 }
@@ -0,0 +1,271 @@
+use std::mem;
+use std::ops::Index;
+
+use crate::world::{CHUNK_VOLUME, LocalPos};
+
+const WORD_BITS: usize = 64;
+
+/// Per-block data for a single chunk, such as the blocks themselves or their
+/// light levels.
+///
+/// Values are stored in a palette of the distinct values in the chunk, with
+/// each cell holding a packed index into the palette using as few bits as the
+/// size of the palette allows. A chunk filled with a single value, such as
+/// all air, only stores its palette.
+#[derive(Clone, Debug)]
+pub struct ChunkData<T> {
+    palette: Vec<T>,
+    /// Bits per palette index: 0 when the chunk is uniform, otherwise a power
+    /// of two so that indices never straddle two words.
+    bits: usize,
+    words: Vec<u64>,
+}
+
+impl<T: Copy + PartialEq> ChunkData<T> {
+    pub fn new(default: T) -> ChunkData<T> {
+        ChunkData {
+            palette: vec![default],
+            bits: 0,
+            words: Vec::new(),
+        }
+    }
+
+    pub fn get(&self, position: LocalPos) -> T {
+        self[position]
+    }
+
+    pub fn set(&mut self, position: LocalPos, value: T) {
+        let palette_index = match self.palette.iter().position(|&entry| entry == value) {
+            Some(palette_index) => palette_index,
+            None => self.add_to_palette(value),
+        };
+
+        if self.bits > 0 {
+            self.set_palette_index(position.index(), palette_index);
+        }
+    }
+
+    /// Determine if every cell holds the same value.
+    pub fn is_uniform(&self) -> bool {
+        self.bits == 0
+    }
+
+    /// Number of distinct values in the palette, including any which are no
+    /// longer used by a cell until the data is compacted.
+    pub fn palette_len(&self) -> usize {
+        self.palette.len()
+    }
+
+    /// Approximate number of bytes used by the data.
+    pub fn memory_usage(&self) -> usize {
+        mem::size_of::<Self>()
+            + self.palette.capacity() * mem::size_of::<T>()
+            + self.words.capacity() * mem::size_of::<u64>()
+    }
+
+    /// Remove values from the palette which are no longer used, shrinking the
+    /// index width if possible.
+    pub fn compact(&mut self) {
+        if self.bits == 0 {
+            return;
+        }
+
+        let mut used = vec![false; self.palette.len()];
+        for i in 0..CHUNK_VOLUME as usize {
+            used[self.palette_index(i)] = true;
+        }
+
+        if used.iter().all(|&used| used) && bits_for(self.palette.len()) == self.bits {
+            return;
+        }
+
+        let values: Vec<T> = (0..CHUNK_VOLUME as usize)
+            .map(|i| self.palette[self.palette_index(i)])
+            .collect();
+        let palette: Vec<T> = self.palette.iter()
+            .zip(used)
+            .filter(|&(_, used)| used)
+            .map(|(&entry, _)| entry)
+            .collect();
+
+        self.repack(palette, &values);
+    }
+
+    /// Add a new value to the palette, widening the indices if they no longer
+    /// fit. Returns the index of the value in the palette.
+    fn add_to_palette(&mut self, value: T) -> usize {
+        if self.palette.len() < 1 << self.bits {
+            self.palette.push(value);
+            return self.palette.len() - 1;
+        }
+
+        // drop values which are no longer used before widening
+        self.compact();
+        if self.palette.len() < 1 << self.bits {
+            self.palette.push(value);
+            return self.palette.len() - 1;
+        }
+
+        let values: Vec<T> = (0..CHUNK_VOLUME as usize)
+            .map(|i| self.palette[self.palette_index(i)])
+            .collect();
+        let mut palette = self.palette.clone();
+        palette.push(value);
+        self.repack(palette, &values);
+
+        self.palette.len() - 1
+    }
+
+    /// Replace the palette and store every value again with the smallest
+    /// index width which fits the palette.
+    fn repack(&mut self, palette: Vec<T>, values: &[T]) {
+        self.bits = bits_for(palette.len());
+        self.palette = palette;
+        self.words = vec![0; CHUNK_VOLUME as usize * self.bits / WORD_BITS];
+
+        if self.bits > 0 {
+            for (i, value) in values.iter().enumerate() {
+                let palette_index = self.palette.iter()
+                    .position(|entry| entry == value)
+                    .expect("value missing from palette");
+                self.set_palette_index(i, palette_index);
+            }
+        }
+    }
+}
+
+impl<T> ChunkData<T> {
+    fn palette_index(&self, i: usize) -> usize {
+        if self.bits == 0 {
+            return 0;
+        }
+
+        let per_word = WORD_BITS / self.bits;
+        let shift = (i % per_word) * self.bits;
+        let mask = (1 << self.bits) - 1;
+
+        ((self.words[i / per_word] >> shift) & mask) as usize
+    }
+
+    fn set_palette_index(&mut self, i: usize, palette_index: usize) {
+        let per_word = WORD_BITS / self.bits;
+        let shift = (i % per_word) * self.bits;
+        let mask: u64 = (1 << self.bits) - 1;
+
+        let word = &mut self.words[i / per_word];
+        *word = (*word & !(mask << shift)) | ((palette_index as u64) << shift);
+    }
+}
+
+/// Smallest power of two number of bits which can index a palette of `len`
+/// values, or 0 if there is only one value.
+pub(crate) fn bits_for(len: usize) -> usize {
+    let mut bits = 0;
+    while 1 << bits < len {
+        bits = if bits == 0 { 1 } else { bits * 2 };
+    }
+    bits
+}
+
+impl<T> Index<LocalPos> for ChunkData<T> {
+    type Output = T;
+
+    fn index(&self, index: LocalPos) -> &Self::Output {
+        &self.palette[self.palette_index(index.index())]
+    }
+}
+
+impl<T: Copy + PartialEq> PartialEq for ChunkData<T> {
+    fn eq(&self, other: &Self) -> bool {
+        LocalPos::all().all(|position| self[position] == other[position])
+    }
+}
+
+impl<T: Copy + Eq> Eq for ChunkData<T> {}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::convert::TryFrom;
+
+    use super::*;
+    use crate::world::block::{self, Block};
+
+    /// Small deterministic xorshift generator, so failures can be reproduced.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, max: u64) -> u64 {
+            self.next() % max
+        }
+    }
+
+    fn assert_matches(data: &ChunkData<Block>, reference: &[Block]) {
+        for position in LocalPos::all() {
+            assert_eq!(data[position], reference[position.index()], "at {:?}", position);
+        }
+
+        // with fewer than 256 distinct blocks the packed indices take at most
+        // half the space of the plain array
+        let plain = mem::size_of_val(reference);
+        assert!(data.memory_usage() < plain, "{} bytes", data.memory_usage());
+    }
+
+    #[test]
+    fn random_edits_match_plain_array() {
+        for seed in 1..=8u64 {
+            let mut rng = Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+            let mut data = ChunkData::new(block::AIR);
+            let mut reference = vec![block::AIR; CHUNK_VOLUME as usize];
+
+            // vary how many distinct blocks are in use over time, so the
+            // palette both widens and shrinks
+            for round in 0..40 {
+                let distinct = [2, 5, 17, 40, 3, 1][round % 6];
+                for _ in 0..rng.below(600) + 1 {
+                    let index = rng.below(CHUNK_VOLUME as u64) as i64;
+                    let block = Block::new(rng.below(distinct) as u16, 0);
+
+                    data.set(LocalPos::try_from(index).unwrap(), block);
+                    reference[index as usize] = block;
+                }
+
+                if round % 4 == 3 {
+                    data.compact();
+
+                    let used: HashSet<Block> = reference.iter().cloned().collect();
+                    assert_eq!(data.palette_len(), used.len());
+                    assert_eq!(data.is_uniform(), used.len() == 1);
+                }
+
+                assert_matches(&data, &reference);
+            }
+        }
+    }
+
+    #[test]
+    fn filling_with_one_value_compacts_to_uniform() {
+        let mut data = ChunkData::new(block::AIR);
+        for position in LocalPos::all() {
+            data.set(position, Block::new(position.index() as u16 % 3, 0));
+        }
+        let mixed = data.memory_usage();
+
+        for position in LocalPos::all() {
+            data.set(position, block::AIR);
+        }
+        data.compact();
+
+        assert!(data.is_uniform());
+        assert_eq!(data.palette_len(), 1);
+        assert!(data.memory_usage() < mixed);
+        assert_matches(&data, &vec![block::AIR; CHUNK_VOLUME as usize]);
+    }
+}
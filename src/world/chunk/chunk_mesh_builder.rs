@@ -0,0 +1,569 @@
+use crate::render_gl::data;
+use crate::render_gl::TextureAtlas;
+use crate::world::{CHUNK_AREA, CHUNK_SIZE, ChunkPos, Position};
+use crate::world::block::{Block, BLOCK_FACES, BlockBounds, BlockFace, BlockRegistry};
+use crate::world::light::{Channel, LightLevel};
+
+use super::padded_chunk_data::PaddedChunkData;
+
+// TODO: replace with block?
+#[derive(VertexAttribPointers, Copy, Clone, Debug)]
+#[repr(C, packed)]
+pub(super) struct Vertex {
+    #[location = 0]
+    pos: data::f32_f32_f32,
+    /// Texture coordinates measured in tiles, repeating every whole tile.
+    #[location = 1]
+    uv: data::f16_f16,
+    /// Sky light and red, green and blue block light, packed as in
+    /// `LightLevel`.
+    #[location = 2]
+    light: data::u4_u4_u4_u4_int,
+    /// Texture coordinates of the tile in the atlas.
+    #[location = 3]
+    tile: data::f16_f16,
+    /// Ambient occlusion of the corner, from 0 (enclosed) to 3 (open).
+    #[location = 4]
+    ambient_occlusion: data::u8_,
+}
+
+/// How block faces are turned into quads when building a chunk mesh.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MeshingMode {
+    /// Every visible block face becomes its own quad.
+    Naive,
+    /// Adjacent coplanar faces of full blocks with the same texture, and the
+    /// same light and ambient occlusion at every corner, are merged into
+    /// larger quads.
+    Greedy,
+}
+
+/// Options controlling how chunk meshes are built.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MeshOptions {
+    pub mode: MeshingMode,
+    /// Light each corner of a face with the average light of the cells around
+    /// it, rather than lighting the whole face evenly.
+    pub smooth_lighting: bool,
+    /// Darken the corners of faces which are enclosed by neighbouring blocks.
+    pub ambient_occlusion: bool,
+}
+
+impl Default for MeshOptions {
+    fn default() -> Self {
+        MeshOptions {
+            mode: MeshingMode::Greedy,
+            smooth_lighting: true,
+            ambient_occlusion: true,
+        }
+    }
+}
+
+/// Ambient occlusion of a corner with no neighbouring blocks around it.
+const OPEN_CORNER: u8 = 3;
+
+/// Texture coordinates of the corners of a single block face, in tiles, in the
+/// same order as `BlockFace::vertices`.
+const FACE_UVS: [(f32, f32); 4] = [(0.0, 1.0), (1.0, 1.0), (1.0, 0.0), (0.0, 0.0)];
+
+/// Everything which must match for two faces to be merged into one quad.
+/// Light and ambient occlusion are given for each corner, in the same order as
+/// `BlockFace::vertices`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct FaceKey {
+    tex_id: u32,
+    light: [LightLevel; 4],
+    ambient_occlusion: [u8; 4],
+}
+
+impl FaceKey {
+    /// Determine if every corner of the face looks the same, so that it can
+    /// be merged without losing any shading.
+    fn is_uniform(&self) -> bool {
+        self.light.iter().all(|&light| light == self.light[0])
+            && self.ambient_occlusion.iter().all(|&ao| ao == self.ambient_occlusion[0])
+    }
+}
+
+/// A rectangle of block faces in one layer, added to the mesh as one quad.
+struct Quad<'a> {
+    block_face: &'a BlockFace,
+    /// The block at the lowest corner of the quad.
+    start: Position,
+    /// Axis and number of faces covered along each of the two axes of the
+    /// layer.
+    u_extent: (usize, i64),
+    v_extent: (usize, i64),
+    /// Each face covers the side of `bounds` rather than the whole cell.
+    bounds: BlockBounds,
+    key: FaceKey,
+}
+
+/// Builds the vertices and indices of a chunk mesh on the CPU.
+///
+/// Holds no GL resources, so meshes can be built on a worker thread and then
+/// uploaded to a `ChunkMesh` on the render thread.
+pub struct ChunkMeshBuilder {
+    atlas: TextureAtlas,
+    options: MeshOptions,
+    pub(super) vertices: Vec<Vertex>,
+    pub(super) indices: Vec<u32>,
+}
+
+impl ChunkMeshBuilder {
+    pub fn new(atlas: TextureAtlas, options: MeshOptions) -> ChunkMeshBuilder {
+        ChunkMeshBuilder {
+            atlas,
+            options,
+            vertices: Vec::new(),
+            indices: Vec::new(),
+        }
+    }
+
+    /// Number of quads in the built mesh.
+    pub fn quad_count(&self) -> usize {
+        self.vertices.len() / 4
+    }
+
+    /// Build the mesh of the chunk at `chunk_position`.
+    ///
+    /// Faces are culled against the neighbouring chunks using the border of
+    /// the padded block data. Cells of neighbours which were not loaded should
+    /// be air, so the faces on that side are drawn; the chunk is meshed again
+    /// once the neighbour is loaded.
+    pub fn build(
+        &mut self,
+        chunk_position: &ChunkPos,
+        block_data: &PaddedChunkData<Block>,
+        light_data: &PaddedChunkData<LightLevel>,
+        registry: &BlockRegistry,
+    ) {
+        self.vertices.clear();
+        self.indices.clear();
+
+        for block_face in &BLOCK_FACES {
+            // faces pointing the same way are built one layer at a time, with
+            // (u, v) being the axes of the layer
+            let normal_axis = axis_of(&block_face.normal);
+            let u_axis = (normal_axis + 1) % 3;
+            let v_axis = (normal_axis + 2) % 3;
+
+            for layer in 0..CHUNK_SIZE {
+                let mut mask: [Option<FaceKey>; CHUNK_AREA as usize] = [None; CHUNK_AREA as usize];
+
+                for v in 0..CHUNK_SIZE {
+                    for u in 0..CHUNK_SIZE {
+                        let mut position = Position::new(0, 0, 0);
+                        position.set(normal_axis, layer);
+                        position.set(u_axis, u);
+                        position.set(v_axis, v);
+
+                        let (key, bounds) = match self.face_key(block_face, position, (u_axis, v_axis), block_data, light_data, registry) {
+                            Some(face) => face,
+                            None => continue,
+                        };
+
+                        // faces of partial blocks such as slabs, and faces
+                        // shaded unevenly, are never merged
+                        if self.options.mode == MeshingMode::Greedy && bounds.is_full() && key.is_uniform() {
+                            mask[(u + v * CHUNK_SIZE) as usize] = Some(key);
+                        } else {
+                            self.add_quad(chunk_position, &Quad {
+                                block_face,
+                                start: position,
+                                u_extent: (u_axis, 1),
+                                v_extent: (v_axis, 1),
+                                bounds,
+                                key,
+                            });
+                        }
+                    }
+                }
+
+                let mut start = Position::new(0, 0, 0);
+                start.set(normal_axis, layer);
+
+                for (u, v, width, height, key) in merge_faces(&mut mask) {
+                    start.set(u_axis, u);
+                    start.set(v_axis, v);
+                    self.add_quad(chunk_position, &Quad {
+                        block_face,
+                        start,
+                        u_extent: (u_axis, width),
+                        v_extent: (v_axis, height),
+                        bounds: BlockBounds::full(),
+                        key,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Determine how the face of the block at `position` should be drawn and
+    /// the bounds of the block, or `None` if the face is hidden. `axes` are the
+    /// two axes of the plane of the face.
+    fn face_key(
+        &self,
+        block_face: &BlockFace,
+        position: Position,
+        axes: (usize, usize),
+        block_data: &PaddedChunkData<Block>,
+        light_data: &PaddedChunkData<LightLevel>,
+        registry: &BlockRegistry,
+    ) -> Option<(FaceKey, BlockBounds)> {
+        let block: Block = block_data[position];
+        let block_type = registry.get(block);
+
+        // Do not render invisible blocks such as AIR.
+        let tex_id = block_type.texture(block_face.direction, block.state())?;
+        let bounds = block_type.bounds(block.state());
+
+        // faces on the edge of the cell are hidden by opaque neighbours, and
+        // between two transparent blocks of the same type such as glass
+        let on_edge = bounds.touches(block_face.direction);
+        if on_edge {
+            let neighbor = block_data[position + block_face.normal];
+            if registry.occludes(neighbor) || (!block_type.opaque && neighbor == block) {
+                return None;
+            }
+        }
+
+        // faces are lit by the cell they face, which is the block's own cell
+        // for faces inside it such as the top of a slab
+        let facing = if on_edge {
+            position + block_face.normal
+        } else {
+            position
+        };
+
+        let mut light = [light_data[facing]; 4];
+        let mut ambient_occlusion = [OPEN_CORNER; 4];
+
+        if self.options.smooth_lighting || self.options.ambient_occlusion {
+            for (i, corner) in block_face.vertices.iter().enumerate() {
+                // the cells beside the facing cell towards the corner, and the
+                // cell diagonally between them
+                let mut side_u = Position::new(0, 0, 0);
+                side_u.set(axes.0, if corner.get(axes.0) == 0 { -1 } else { 1 });
+                let mut side_v = Position::new(0, 0, 0);
+                side_v.set(axes.1, if corner.get(axes.1) == 0 { -1 } else { 1 });
+
+                let side_cells = [facing + side_u, facing + side_v];
+                let diagonal_cell = facing + side_u + side_v;
+                let sides = [
+                    registry.occludes(block_data[side_cells[0]]),
+                    registry.occludes(block_data[side_cells[1]]),
+                ];
+                let diagonal = registry.occludes(block_data[diagonal_cell]);
+
+                if self.options.ambient_occlusion {
+                    ambient_occlusion[i] = corner_occlusion(sides[0], sides[1], diagonal);
+                }
+
+                if self.options.smooth_lighting {
+                    // light can not reach the diagonal cell if both sides are
+                    // blocked
+                    let mut lights = [light_data[facing]; 4];
+                    let mut count = 1;
+                    for (&cell, &occluded) in side_cells.iter().zip(&sides) {
+                        if !occluded {
+                            lights[count] = light_data[cell];
+                            count += 1;
+                        }
+                    }
+                    let diagonal_blocked = diagonal || (sides[0] && sides[1]);
+                    if !diagonal_blocked {
+                        lights[count] = light_data[diagonal_cell];
+                        count += 1;
+                    }
+
+                    light[i] = average_light(&lights[..count]);
+                }
+            }
+        }
+
+        Some((
+            FaceKey {
+                tex_id,
+                light,
+                ambient_occlusion,
+            },
+            bounds,
+        ))
+    }
+
+    /// Add a quad to the mesh of the chunk at `chunk_position`.
+    fn add_quad(&mut self, chunk_position: &ChunkPos, quad: &Quad) {
+        let Quad { block_face, start, u_extent, v_extent, bounds, key } = *quad;
+        let face_vertices = &block_face.vertices;
+        let origin = Position::from(chunk_position.origin()) + start;
+        let index = self.vertices.len() as u32;
+
+        // number of cells covered along each axis
+        let mut extents = [1; 3];
+        extents[u_extent.0] = u_extent.1;
+        extents[v_extent.0] = v_extent.1;
+        let size = |axis: usize| (extents[axis] - 1) as f32 + bounds.max[axis] - bounds.min[axis];
+
+        // the horizontal texture axis runs between the last two vertices
+        let texture_x_axis = axis_of(&(face_vertices[2] - face_vertices[3]));
+        let (uv_width, uv_height) = if texture_x_axis == u_extent.0 {
+            (size(u_extent.0), size(v_extent.0))
+        } else {
+            (size(v_extent.0), size(u_extent.0))
+        };
+
+        let tile = self.atlas.tile_origin(key.tex_id);
+
+        for i in 0..4 {
+            let corner = face_vertices[i];
+            let mut pos = [0.0; 3];
+            for axis in 0..3 {
+                let offset = if corner.get(axis) == 0 {
+                    bounds.min[axis]
+                } else {
+                    (extents[axis] - 1) as f32 + bounds.max[axis]
+                };
+                pos[axis] = origin.get(axis) as f32 + offset;
+            }
+
+            self.vertices.push(Vertex {
+                pos: data::f32_f32_f32::new(pos[0], pos[1], pos[2]),
+                uv: data::f16_f16::from((
+                    FACE_UVS[i].0 * uv_width,
+                    FACE_UVS[i].1 * uv_height,
+                )),
+                light: data::u4_u4_u4_u4_int::new(key.light[i].to_raw()),
+                tile,
+                ambient_occlusion: data::u8_::new(key.ambient_occlusion[i]),
+            });
+        }
+
+        // split the quad along the diagonal between its darker corners, so
+        // that the occlusion is interpolated evenly
+        let ao = key.ambient_occlusion;
+        let first = if ao[0] + ao[2] > ao[1] + ao[3] { 1 } else { 0 };
+        for &corner in &[0, 1, 2, 2, 3, 0] {
+            self.indices.push(index + (first + corner) % 4);
+        }
+    }
+}
+
+/// Greedily merge a layer of faces into rectangles, returning the start,
+/// width, height and key of each rectangle. The mask is cleared.
+fn merge_faces(mask: &mut [Option<FaceKey>]) -> Vec<(i64, i64, i64, i64, FaceKey)> {
+    let index = |u: i64, v: i64| (u + v * CHUNK_SIZE) as usize;
+    let mut quads = Vec::new();
+
+    for v in 0..CHUNK_SIZE {
+        let mut u = 0;
+        while u < CHUNK_SIZE {
+            let key = match mask[index(u, v)] {
+                Some(key) => key,
+                None => {
+                    u += 1;
+                    continue;
+                }
+            };
+
+            // extend along u as far as possible...
+            let mut width = 1;
+            while u + width < CHUNK_SIZE && mask[index(u + width, v)] == Some(key) {
+                width += 1;
+            }
+
+            // ...then along v while the whole row matches
+            let mut height = 1;
+            'grow: while v + height < CHUNK_SIZE {
+                for k in 0..width {
+                    if mask[index(u + k, v + height)] != Some(key) {
+                        break 'grow;
+                    }
+                }
+                height += 1;
+            }
+
+            for dv in 0..height {
+                for du in 0..width {
+                    mask[index(u + du, v + dv)] = None;
+                }
+            }
+
+            quads.push((u, v, width, height, key));
+            u += width;
+        }
+    }
+
+    quads
+}
+
+/// Ambient occlusion of a corner from whether the two cells beside it and the
+/// cell diagonally between them are occupied, from 0 (enclosed) to 3 (open).
+fn corner_occlusion(side_u: bool, side_v: bool, diagonal: bool) -> u8 {
+    if side_u && side_v {
+        0
+    } else {
+        OPEN_CORNER - side_u as u8 - side_v as u8 - diagonal as u8
+    }
+}
+
+/// Average each channel of the light of some cells, rounding to the nearest
+/// level.
+fn average_light(lights: &[LightLevel]) -> LightLevel {
+    let count = lights.len() as u32;
+
+    Channel::ALL.iter().fold(LightLevel::default(), |average, &channel| {
+        let total: u32 = lights.iter().map(|light| light.get(channel) as u32).sum();
+        average.with(channel, ((total + count / 2) / count) as u8)
+    })
+}
+
+/// Index of the only non-zero component of an axis-aligned vector.
+fn axis_of(vector: &Position) -> usize {
+    if vector.x != 0 {
+        0
+    } else if vector.y != 0 {
+        1
+    } else {
+        2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra as na;
+
+    use super::*;
+    use crate::world::{ChunkData, LocalPos};
+    use crate::world::block::AIR;
+    use crate::world::light::{LightColor, SUNLIGHT};
+
+    fn registry() -> BlockRegistry {
+        include_str!("../../../assets/blocks.ron").parse().unwrap()
+    }
+
+    fn build(
+        registry: &BlockRegistry,
+        block_data: &ChunkData<Block>,
+        light_data: &ChunkData<LightLevel>,
+        options: MeshOptions,
+    ) -> ChunkMeshBuilder {
+        let mut builder = ChunkMeshBuilder::new(TextureAtlas::new(16), options);
+        builder.build(
+            &ChunkPos::new(0, 0, 0),
+            &PaddedChunkData::from_chunk(AIR, block_data),
+            &PaddedChunkData::from_chunk(LightLevel::new(SUNLIGHT, LightColor::default()), light_data),
+            registry,
+        );
+        builder
+    }
+
+    fn position(vertex: &Vertex) -> na::Vector3<f32> {
+        let pos = vertex.pos;
+        na::Vector3::new(pos.d0, pos.d1, pos.d2)
+    }
+
+    /// Total area covered by the quads of a mesh.
+    fn area(builder: &ChunkMeshBuilder) -> f32 {
+        builder.vertices.chunks(4)
+            .map(|quad| {
+                let origin = position(&quad[0]);
+                (position(&quad[1]) - origin).cross(&(position(&quad[3]) - origin)).norm()
+            })
+            .sum()
+    }
+
+    #[test]
+    fn greedy_meshing_covers_the_same_area_with_fewer_quads() {
+        let registry = registry();
+        let stone = registry.require("stone").unwrap();
+        let dirt = registry.require("dirt").unwrap();
+        let slab = registry.require("stone_slab").unwrap();
+
+        let mut block_data = ChunkData::new(AIR);
+        for x in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                for z in 0..4 {
+                    let block = if z == 3 && (x + y) % 5 == 0 { dirt } else { stone };
+                    block_data.set(LocalPos::new(x, y, z), block);
+                }
+            }
+        }
+        block_data.set(LocalPos::new(4, 4, 4), stone);
+        block_data.set(LocalPos::new(9, 2, 4), slab);
+        block_data.set(LocalPos::new(5, 12, 7), dirt);
+
+        let light_data = ChunkData::new(LightLevel::new(SUNLIGHT, LightColor::default()));
+
+        for &(smooth_lighting, ambient_occlusion) in &[(false, false), (true, true)] {
+            let options = |mode| MeshOptions { mode, smooth_lighting, ambient_occlusion };
+            let naive = build(&registry, &block_data, &light_data, options(MeshingMode::Naive));
+            let greedy = build(&registry, &block_data, &light_data, options(MeshingMode::Greedy));
+
+            assert!(greedy.quad_count() <= naive.quad_count());
+            assert!(greedy.quad_count() < naive.quad_count() / 2);
+            assert!((area(&greedy) - area(&naive)).abs() < 1e-2);
+            assert_eq!(greedy.indices.len(), greedy.quad_count() * 6);
+        }
+    }
+
+    #[test]
+    fn corners_are_shaded_by_their_neighbours() {
+        let registry = registry();
+        let stone = registry.require("stone").unwrap();
+
+        // a block with occluders beside the +x and +y edges of its top face
+        let mut block_data = ChunkData::new(AIR);
+        block_data.set(LocalPos::new(8, 8, 8), stone);
+        block_data.set(LocalPos::new(9, 8, 9), stone);
+        block_data.set(LocalPos::new(8, 9, 9), stone);
+
+        // a brighter, red cell diagonally beside the -x -y corner
+        let mut light_data = ChunkData::new(LightLevel::new(8, LightColor::default()));
+        light_data.set(LocalPos::new(7, 7, 9), LightLevel::new(12, LightColor(4, 0, 0)));
+
+        let builder = build(&registry, &block_data, &light_data, MeshOptions {
+            mode: MeshingMode::Naive,
+            smooth_lighting: true,
+            ambient_occlusion: true,
+        });
+
+        let quad = builder.vertices.chunks(4)
+            .position(|quad| {
+                let centre = quad.iter().map(position).sum::<na::Vector3<f32>>() / 4.0;
+                (centre - na::Vector3::new(8.5, 8.5, 9.0)).norm() < 1e-4
+            })
+            .expect("top face is missing");
+
+        for vertex in &builder.vertices[quad * 4..quad * 4 + 4] {
+            let corner = position(vertex);
+            let (expected_ao, expected_light) = match (corner.x as i64, corner.y as i64) {
+                // open, with the diagonal cell averaged in
+                (8, 8) => (3, LightLevel::new(9, LightColor(1, 0, 0))),
+                // beside one occluder
+                (9, 8) | (8, 9) => (2, LightLevel::new(8, LightColor::default())),
+                // between both occluders, lit only by the facing cell
+                (9, 9) => (0, LightLevel::new(8, LightColor::default())),
+                _ => panic!("unexpected corner {:?}", corner),
+            };
+
+            let ambient_occlusion = vertex.ambient_occlusion.inner;
+            let light = LightLevel::from_raw(vertex.light.inner);
+            assert_eq!(ambient_occlusion, expected_ao, "at {:?}", corner);
+            assert_eq!(light, expected_light, "at {:?}", corner);
+        }
+
+        // the quad is split along the diagonal between the open corner and
+        // the enclosed corner, which are shared by both triangles
+        let indices = &builder.indices[quad * 6..quad * 6 + 6];
+        let mut shared: Vec<(i64, i64)> = indices[..3].iter()
+            .filter(|index| indices[3..].contains(index))
+            .map(|&index| {
+                let corner = position(&builder.vertices[index as usize]);
+                (corner.x as i64, corner.y as i64)
+            })
+            .collect();
+        shared.sort();
+        assert_eq!(shared, vec![(8, 8), (9, 9)]);
+    }
+}
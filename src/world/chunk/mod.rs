@@ -1,4 +1,11 @@
 pub use chunk::Chunk;
+pub use chunk_data::ChunkData;
+pub use chunk_mesh_builder::{ChunkMeshBuilder, MeshingMode, MeshOptions};
+pub use padded_chunk_data::PaddedChunkData;
+pub(crate) use chunk_data::bits_for;
 
 mod chunk;
+mod chunk_data;
 mod chunk_mesh;
+mod chunk_mesh_builder;
+mod padded_chunk_data;
@@ -0,0 +1,111 @@
+use std::ops::Index;
+
+use crate::world::{CHUNK_SIZE, ChunkPos, LocalPos, Position};
+
+use super::chunk_data::ChunkData;
+
+/// Number of blocks along each axis of padded chunk data.
+pub const PADDED_SIZE: i64 = CHUNK_SIZE + 2;
+
+/// A copy of chunk data with a border one block wide taken from the
+/// neighbouring chunks.
+///
+/// Lets the mesher look up the neighbours of blocks on the chunk border
+/// without access to the rest of the world. Positions are relative to the
+/// origin of the centre chunk, so each coordinate ranges from `-1` to
+/// `CHUNK_SIZE` inclusive.
+#[derive(Clone, Debug)]
+pub struct PaddedChunkData<T> {
+    data: Vec<T>,
+}
+
+impl<T: Copy> PaddedChunkData<T> {
+    /// Copy the data of the centre chunk and the border of its neighbours.
+    ///
+    /// `get_chunk` is called with the offset of each neighbour from the centre
+    /// chunk (and with the zero offset for the centre chunk itself). Cells in
+    /// chunks for which it returns `None` are filled with `default`.
+    pub fn from_chunks<'a, F>(default: T, get_chunk: F) -> PaddedChunkData<T>
+        where F: Fn(&ChunkPos) -> Option<&'a ChunkData<T>>,
+              T: 'a,
+    {
+        // look up each of the 27 chunks once
+        let mut chunks = Vec::with_capacity(27);
+        for z in -1..=1 {
+            for y in -1..=1 {
+                for x in -1..=1 {
+                    chunks.push(get_chunk(&ChunkPos::new(x, y, z)));
+                }
+            }
+        }
+
+        let mut data = Vec::with_capacity((PADDED_SIZE * PADDED_SIZE * PADDED_SIZE) as usize);
+        for z in -1..=CHUNK_SIZE {
+            for y in -1..=CHUNK_SIZE {
+                for x in -1..=CHUNK_SIZE {
+                    let chunk_offset = Position::new(
+                        x.div_euclid(CHUNK_SIZE),
+                        y.div_euclid(CHUNK_SIZE),
+                        z.div_euclid(CHUNK_SIZE),
+                    );
+                    let chunk_index = (chunk_offset.x + 1)
+                        + (chunk_offset.y + 1) * 3
+                        + (chunk_offset.z + 1) * 9;
+
+                    let value = match chunks[chunk_index as usize] {
+                        Some(chunk_data) => chunk_data[LocalPos::new(
+                            x.rem_euclid(CHUNK_SIZE),
+                            y.rem_euclid(CHUNK_SIZE),
+                            z.rem_euclid(CHUNK_SIZE),
+                        )],
+                        None => default,
+                    };
+                    data.push(value);
+                }
+            }
+        }
+
+        PaddedChunkData {
+            data,
+        }
+    }
+
+    /// Pad the data of a single chunk with `default`, as if none of its
+    /// neighbours were loaded.
+    pub fn from_chunk(default: T, chunk_data: &ChunkData<T>) -> PaddedChunkData<T> {
+        PaddedChunkData::from_chunks(default, |offset| {
+            if *offset == ChunkPos::new(0, 0, 0) {
+                Some(chunk_data)
+            } else {
+                None
+            }
+        })
+    }
+}
+
+impl<T> Index<Position> for PaddedChunkData<T> {
+    type Output = T;
+
+    /// Panics if the position lies outside of the padded area.
+    fn index(&self, position: Position) -> &Self::Output {
+        let range = -1..=CHUNK_SIZE;
+        assert!(
+            range.contains(&position.x) && range.contains(&position.y) && range.contains(&position.z),
+            "position {:?} is outside of the padded chunk", position,
+        );
+
+        let index = (position.x + 1)
+            + (position.y + 1) * PADDED_SIZE
+            + (position.z + 1) * PADDED_SIZE * PADDED_SIZE;
+
+        &self.data[index as usize]
+    }
+}
+
+impl<T> Index<LocalPos> for PaddedChunkData<T> {
+    type Output = T;
+
+    fn index(&self, position: LocalPos) -> &Self::Output {
+        &self[Position::from(position)]
+    }
+}
@@ -0,0 +1,163 @@
+use std::f32::consts::PI;
+
+use nalgebra as na;
+
+/// Default length of a whole day, in seconds.
+const DAY_LENGTH: f32 = 20.0 * 60.0;
+
+/// Darkest the sky light gets at midnight, as a fraction of full daylight.
+const NIGHT_SKY_LIGHT: f32 = 0.2;
+
+const NIGHT_COLOR: [f32; 3] = [0.02, 0.02, 0.08];
+const DAY_COLOR: [f32; 3] = [0.45, 0.65, 0.95];
+const HORIZON_COLOR: [f32; 3] = [0.9, 0.5, 0.3];
+
+/// Time of day in the world, advanced by the frame time.
+///
+/// Time is a fraction of a day, where 0 is midnight, 0.25 is sunrise, 0.5 is
+/// noon and 0.75 is sunset.
+pub struct WorldClock {
+    time: f32,
+    /// Length of a whole day, in seconds.
+    pub day_length: f32,
+    /// A frozen clock is not advanced, but its time can still be set.
+    pub frozen: bool,
+}
+
+impl WorldClock {
+    pub fn new(time: f32) -> WorldClock {
+        WorldClock {
+            time: wrap_time(time),
+            day_length: DAY_LENGTH,
+            frozen: false,
+        }
+    }
+
+    /// Advance the clock by `delta` seconds, unless it is frozen.
+    pub fn advance(&mut self, delta: f32) {
+        if !self.frozen {
+            self.set_time(self.time + delta / self.day_length);
+        }
+    }
+
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    /// Set the time of day, wrapping it into a single day.
+    pub fn set_time(&mut self, time: f32) {
+        self.time = wrap_time(time);
+    }
+
+    /// Height of the sun, from -1 at midnight to 1 at noon.
+    pub fn sun_height(&self) -> f32 {
+        -(self.time * 2.0 * PI).cos()
+    }
+
+    /// Multiplier applied to sky light, from `NIGHT_SKY_LIGHT` at night to 1
+    /// during the day.
+    pub fn sky_light(&self) -> f32 {
+        NIGHT_SKY_LIGHT + (1.0 - NIGHT_SKY_LIGHT) * self.daylight()
+    }
+
+    /// Colour of the sky, which is also used for fog.
+    pub fn sky_color(&self) -> na::Vector3<f32> {
+        let night = na::Vector3::from(NIGHT_COLOR);
+        let day = na::Vector3::from(DAY_COLOR);
+        let horizon = na::Vector3::from(HORIZON_COLOR);
+
+        // the sky glows around sunrise and sunset
+        let glow = (1.0 - self.sun_height().abs() / 0.25).max(0.0) * 0.5;
+
+        night.lerp(&day, self.daylight()).lerp(&horizon, glow)
+    }
+
+    /// How far from night (0) to day (1) it is, changing smoothly while the
+    /// sun is near the horizon.
+    fn daylight(&self) -> f32 {
+        smoothstep(-0.2, 0.3, self.sun_height())
+    }
+}
+
+/// Wrap a time into a single day, from 0 up to but not including 1. Times
+/// which are not finite become midnight.
+fn wrap_time(time: f32) -> f32 {
+    let time = time.rem_euclid(1.0);
+
+    // tiny negative times round up to a whole day
+    if time < 1.0 {
+        time
+    } else {
+        0.0
+    }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-4, "{} is not {}", a, b);
+    }
+
+    #[test]
+    fn sky_light_is_full_at_noon_and_dim_at_midnight() {
+        let mut clock = WorldClock::new(0.5);
+        assert_close(clock.sun_height(), 1.0);
+        assert_close(clock.sky_light(), 1.0);
+        assert_close(clock.sky_color().x, DAY_COLOR[0]);
+
+        clock.set_time(0.0);
+        assert_close(clock.sun_height(), -1.0);
+        assert_close(clock.sky_light(), NIGHT_SKY_LIGHT);
+        assert_close(clock.sky_color().z, NIGHT_COLOR[2]);
+
+        // the light changes gradually around sunrise
+        clock.set_time(0.25);
+        assert!(clock.sky_light() > NIGHT_SKY_LIGHT && clock.sky_light() < 1.0);
+    }
+
+    #[test]
+    fn advancing_wraps_past_a_whole_day() {
+        let mut clock = WorldClock::new(0.75);
+        clock.day_length = 100.0;
+
+        clock.advance(50.0);
+        assert_close(clock.time(), 0.25);
+
+        clock.advance(1000.0);
+        assert_close(clock.time(), 0.25);
+    }
+
+    #[test]
+    fn frozen_clocks_do_not_advance() {
+        let mut clock = WorldClock::new(0.25);
+        clock.frozen = true;
+
+        clock.advance(clock.day_length / 2.0);
+        assert_eq!(clock.time(), 0.25);
+
+        clock.set_time(0.5);
+        assert_eq!(clock.time(), 0.5);
+    }
+
+    #[test]
+    fn times_outside_a_day_are_wrapped() {
+        let mut clock = WorldClock::new(0.0);
+
+        for &(time, wrapped) in &[(-0.25, 0.75), (-3.5, 0.5), (1.0, 0.0), (7.25, 0.25), (1e9, 0.0)] {
+            clock.set_time(time);
+            assert_close(clock.time(), wrapped);
+        }
+
+        for &time in &[-1e-9, -1e9, f32::MAX, f32::MIN, f32::INFINITY, f32::NAN] {
+            clock.set_time(time);
+            assert!((0.0..1.0).contains(&clock.time()), "{} wrapped to {}", time, clock.time());
+        }
+    }
+}
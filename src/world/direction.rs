@@ -1,3 +1,6 @@
+use crate::world::Position;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Direction {
     North,
     East,
@@ -6,3 +9,17 @@ pub enum Direction {
     Top,
     Bottom,
 }
+
+impl Direction {
+    /// Offset to the neighbouring cell in this direction.
+    pub fn normal(&self) -> Position {
+        match self {
+            Direction::North => Position::new(0, 1, 0),
+            Direction::East => Position::new(1, 0, 0),
+            Direction::South => Position::new(0, -1, 0),
+            Direction::West => Position::new(-1, 0, 0),
+            Direction::Top => Position::new(0, 0, 1),
+            Direction::Bottom => Position::new(0, 0, -1),
+        }
+    }
+}
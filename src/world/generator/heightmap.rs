@@ -0,0 +1,143 @@
+use noise::{Fbm, MultiFractal, NoiseFn, Seedable};
+
+use crate::world::{CHUNK_SIZE, ChunkData, ChunkPos, LocalPos};
+use crate::world::block::{self, Block, BlockRegistry};
+
+use super::TerrainGenerator;
+
+/// Depth of the dirt layer below the grass.
+const DIRT_DEPTH: i64 = 3;
+
+/// Generates rolling hills from a 2D heightmap.
+///
+/// The height of each column is sampled from fractal Brownian motion: several
+/// octaves of Perlin noise layered at increasing frequency and decreasing
+/// amplitude.
+pub struct HeightmapGenerator {
+    seed: u32,
+    noise: Fbm,
+    grass: Block,
+    dirt: Block,
+    stone: Block,
+    /// Average height of the terrain surface.
+    pub base_height: f64,
+    /// Maximum distance of the surface above or below the base height.
+    pub amplitude: f64,
+    /// Horizontal size of a block in noise space. Smaller values give
+    /// smoother terrain.
+    pub scale: f64,
+}
+
+impl HeightmapGenerator {
+    /// Create a generator, looking up the blocks it places in the registry.
+    pub fn new(seed: u32, registry: &BlockRegistry) -> Result<HeightmapGenerator, block::Error> {
+        Ok(HeightmapGenerator {
+            seed,
+            noise: Fbm::new()
+                .set_seed(seed)
+                .set_octaves(4)
+                .set_frequency(1.0)
+                .set_persistence(0.5),
+            grass: registry.require("grass")?,
+            dirt: registry.require("dirt")?,
+            stone: registry.require("stone")?,
+            base_height: 20.0,
+            amplitude: 16.0,
+            scale: 1.0 / 64.0,
+        })
+    }
+
+    pub fn seed(&self) -> u32 {
+        self.seed
+    }
+
+    /// Height of the terrain surface at a world column: the z coordinate of
+    /// the topmost solid block.
+    pub fn height_at(&self, x: i64, y: i64) -> i64 {
+        let sample = self.noise.get([x as f64 * self.scale, y as f64 * self.scale]);
+
+        (self.base_height + sample * self.amplitude).floor() as i64
+    }
+}
+
+impl TerrainGenerator for HeightmapGenerator {
+    fn generate(&self, position: ChunkPos, block_data: &mut ChunkData<Block>) {
+        let origin = position.origin();
+
+        for x in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                let height = self.height_at(origin.x + x, origin.y + y);
+
+                for z in 0..CHUNK_SIZE {
+                    let depth = height - (origin.z + z);
+                    let block = match depth {
+                        d if d < 0 => continue,
+                        0 => self.grass,
+                        d if d <= DIRT_DEPTH => self.dirt,
+                        _ => self.stone,
+                    };
+
+                    block_data.set(LocalPos::new(x, y, z), block);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::block::AIR;
+
+    const SEED: u32 = 1;
+
+    fn registry() -> BlockRegistry {
+        include_str!("../../../assets/blocks.ron").parse().unwrap()
+    }
+
+    fn generate(generator: &HeightmapGenerator, position: ChunkPos) -> ChunkData<Block> {
+        let mut block_data = ChunkData::new(AIR);
+        generator.generate(position, &mut block_data);
+        block_data
+    }
+
+    // `ChunkData` equality compares the block in every cell rather than how
+    // the blocks are packed, so these tests check that the same terrain is
+    // generated, not that it is stored in the same layout.
+
+    #[test]
+    fn generation_is_deterministic() {
+        let registry = registry();
+        let generator = HeightmapGenerator::new(SEED, &registry).unwrap();
+        let position = ChunkPos::new(-3, 5, 1);
+
+        assert_eq!(generate(&generator, position), generate(&generator, position));
+
+        let other = HeightmapGenerator::new(SEED, &registry).unwrap();
+        assert_eq!(generate(&generator, position), generate(&other, position));
+    }
+
+    #[test]
+    fn generation_does_not_depend_on_order() {
+        let registry = registry();
+        let positions: Vec<ChunkPos> = (-2..2)
+            .flat_map(|x| (-1..3).map(move |z| ChunkPos::new(x, 1 - x, z)))
+            .collect();
+
+        let forwards = HeightmapGenerator::new(SEED, &registry).unwrap();
+        let forwards: Vec<ChunkData<Block>> = positions.iter()
+            .map(|&position| generate(&forwards, position))
+            .collect();
+
+        let backwards = HeightmapGenerator::new(SEED, &registry).unwrap();
+        let mut backwards: Vec<ChunkData<Block>> = positions.iter()
+            .rev()
+            .map(|&position| generate(&backwards, position))
+            .collect();
+        backwards.reverse();
+
+        assert_eq!(forwards, backwards);
+        // the surface passes through these chunks, so they are not all air
+        assert!(forwards.iter().any(|block_data| !block_data.is_uniform()));
+    }
+}
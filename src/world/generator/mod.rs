@@ -0,0 +1,5 @@
+pub use heightmap::HeightmapGenerator;
+pub use terrain_generator::TerrainGenerator;
+
+mod heightmap;
+mod terrain_generator;
@@ -0,0 +1,14 @@
+use crate::world::{ChunkData, ChunkPos};
+use crate::world::block::Block;
+
+/// Procedurally fills chunks with blocks.
+///
+/// Generation must be deterministic: the same generator settings and chunk
+/// position always produce identical block data. Generators are shared between
+/// the chunk worker threads.
+pub trait TerrainGenerator: Send + Sync {
+    /// Fill the block data of the chunk at the given position.
+    ///
+    /// `block_data` is initially filled with air.
+    fn generate(&self, position: ChunkPos, block_data: &mut ChunkData<Block>);
+}
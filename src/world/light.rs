@@ -1,3 +0,0 @@
-pub type LightLevel = u8;
-
-pub const SUNLIGHT: LightLevel = 15;
@@ -0,0 +1,482 @@
+use std::collections::VecDeque;
+
+use crate::world::{BlockPos, CHUNK_SIZE, ChunkData, ChunkPos, Direction, LocalPos, Position};
+use crate::world::block::{Block, BLOCK_FACES, BlockRegistry};
+
+use super::light_level::{Channel, LightColor, LightLevel, MAX_LIGHT, SUNLIGHT};
+
+/// Access to the blocks and light of the part of the world being lit.
+///
+/// Positions outside of it, such as in chunks which are not loaded, return
+/// `None` and are left unlit.
+pub trait LightAccess {
+    fn block(&self, position: BlockPos) -> Option<Block>;
+
+    fn light(&self, position: BlockPos) -> Option<LightLevel>;
+
+    fn set_light(&mut self, position: BlockPos, light: LightLevel);
+}
+
+/// Light access limited to a single chunk, for lighting a chunk before it is
+/// added to the world.
+struct ChunkLightAccess<'a> {
+    position: ChunkPos,
+    block_data: &'a ChunkData<Block>,
+    light_data: &'a mut ChunkData<LightLevel>,
+}
+
+impl ChunkLightAccess<'_> {
+    fn local_pos(&self, position: BlockPos) -> Option<LocalPos> {
+        let (chunk_position, local_position) = position.split();
+
+        if chunk_position == self.position {
+            Some(local_position)
+        } else {
+            None
+        }
+    }
+}
+
+impl LightAccess for ChunkLightAccess<'_> {
+    fn block(&self, position: BlockPos) -> Option<Block> {
+        self.local_pos(position).map(|position| self.block_data[position])
+    }
+
+    fn light(&self, position: BlockPos) -> Option<LightLevel> {
+        self.local_pos(position).map(|position| self.light_data[position])
+    }
+
+    fn set_light(&mut self, position: BlockPos, light: LightLevel) {
+        if let Some(position) = self.local_pos(position) {
+            self.light_data.set(position, light);
+        }
+    }
+}
+
+/// Light a chunk on its own, as if every neighbour was missing and the chunk
+/// was open to the sky above. `join_chunk` corrects the light once the chunk
+/// is added to the world.
+pub fn light_chunk(
+    registry: &BlockRegistry,
+    position: ChunkPos,
+    block_data: &ChunkData<Block>,
+    light_data: &mut ChunkData<LightLevel>,
+) {
+    let mut access = ChunkLightAccess { position, block_data, light_data };
+    let origin = position.origin();
+    let mut queue = VecDeque::new();
+
+    // sunlight falls down each column until it is blocked
+    for x in 0..CHUNK_SIZE {
+        for y in 0..CHUNK_SIZE {
+            for z in (0..CHUNK_SIZE).rev() {
+                let block_position = origin + Position::new(x, y, z);
+                if registry.occludes(access.block_data[LocalPos::new(x, y, z)]) {
+                    break;
+                }
+
+                access.set_light(block_position, LightLevel::new(SUNLIGHT, LightColor::default()));
+                queue.push_back((block_position, Channel::Sky));
+            }
+        }
+    }
+
+    // blocks which emit light light themselves
+    for local_position in LocalPos::all() {
+        let block_position = origin + Position::from(local_position);
+        for &channel in &Channel::BLOCK {
+            if emit(&mut access, registry, block_position, channel) {
+                queue.push_back((block_position, channel));
+            }
+        }
+    }
+
+    propagate(&mut access, registry, queue);
+    access.light_data.compact();
+}
+
+/// Relight the world around a block which has just changed.
+pub fn update_block<A: LightAccess>(access: &mut A, registry: &BlockRegistry, position: BlockPos) {
+    let old_light = match access.light(position) {
+        Some(light) => light,
+        None => return,
+    };
+
+    // take away the light which passed through the cell...
+    access.set_light(position, LightLevel::default());
+    let mut removal = VecDeque::new();
+    for &channel in &Channel::ALL {
+        if old_light.get(channel) > 0 {
+            removal.push_back((position, channel, old_light.get(channel)));
+        }
+    }
+    let mut queue = remove(access, registry, removal);
+
+    // ...then let the neighbours and the block itself light it again
+    for block_face in &BLOCK_FACES {
+        for &channel in &Channel::ALL {
+            queue.push_back((position + block_face.normal, channel));
+        }
+    }
+
+    for &channel in &Channel::BLOCK {
+        if emit(access, registry, position, channel) {
+            queue.push_back((position, channel));
+        }
+    }
+
+    // the top of a column below an unloaded chunk is assumed to be open sky
+    let block = access.block(position).unwrap();
+    if access.light(position + Position::new(0, 0, 1)).is_none() && !registry.occludes(block) {
+        let light = access.light(position).unwrap();
+        access.set_light(position, light.with(Channel::Sky, SUNLIGHT));
+        queue.push_back((position, Channel::Sky));
+    }
+
+    propagate(access, registry, queue);
+}
+
+/// Spread light between a chunk which has just been added to the world and
+/// its loaded neighbours.
+///
+/// Chunks are lit as if they were open to the sky, so sunlight is first taken
+/// away from columns which turn out to be covered by the chunk above.
+pub fn join_chunk<A: LightAccess>(access: &mut A, registry: &BlockRegistry, position: ChunkPos) {
+    let mut removal = VecDeque::new();
+
+    let above = position + Position::new(0, 0, 1);
+    let below = position + Position::new(0, 0, -1);
+
+    for &(upper, lower) in &[(above, position), (position, below)] {
+        for x in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                let above = upper.block_pos(LocalPos::new(x, y, 0));
+                let below = lower.block_pos(LocalPos::new(x, y, CHUNK_SIZE - 1));
+
+                let (above_light, below_light) = match (access.light(above), access.light(below)) {
+                    (Some(above_light), Some(below_light)) => (above_light, below_light),
+                    _ => continue,
+                };
+
+                if below_light.sky() == SUNLIGHT && above_light.sky() != SUNLIGHT {
+                    access.set_light(below, below_light.with(Channel::Sky, 0));
+                    removal.push_back((below, Channel::Sky, SUNLIGHT));
+                }
+            }
+        }
+    }
+
+    let mut queue = remove(access, registry, removal);
+
+    // light on either side of each face of the chunk can now cross it
+    let origin = position.origin();
+    for block_face in &BLOCK_FACES {
+        for cell in border_cells(block_face.direction) {
+            let inside = origin + cell;
+            let outside = inside + block_face.normal;
+
+            for &channel in &Channel::ALL {
+                queue.push_back((inside, channel));
+                queue.push_back((outside, channel));
+            }
+        }
+    }
+
+    propagate(access, registry, queue);
+}
+
+/// Raise a block light channel of a cell to the level emitted by its block,
+/// if it emits any. Returns `true` if the light changed.
+fn emit<A: LightAccess>(
+    access: &mut A,
+    registry: &BlockRegistry,
+    position: BlockPos,
+    channel: Channel,
+) -> bool {
+    let emission = match access.block(position) {
+        Some(block) => registry.get(block).light_emission.get(channel),
+        None => return false,
+    };
+
+    let light = access.light(position).unwrap();
+    if emission <= light.get(channel) {
+        return false;
+    }
+
+    access.set_light(position, light.with(channel, emission));
+    true
+}
+
+/// Level of light passed from a cell with the given level to its neighbour in
+/// `direction`. Full sunlight travels straight down without fading.
+fn spread_level(channel: Channel, direction: Direction, level: u8) -> u8 {
+    if channel == Channel::Sky && direction == Direction::Bottom && level == MAX_LIGHT {
+        MAX_LIGHT
+    } else {
+        level.saturating_sub(1)
+    }
+}
+
+/// Flood light outwards from every cell in the queue.
+fn propagate<A: LightAccess>(
+    access: &mut A,
+    registry: &BlockRegistry,
+    mut queue: VecDeque<(BlockPos, Channel)>,
+) {
+    while let Some((position, channel)) = queue.pop_front() {
+        let level = match access.light(position) {
+            Some(light) => light.get(channel),
+            None => continue,
+        };
+        if level <= 1 {
+            continue;
+        }
+
+        for block_face in &BLOCK_FACES {
+            let neighbor = position + block_face.normal;
+            match access.block(neighbor) {
+                Some(block) if !registry.occludes(block) => {}
+                _ => continue,
+            }
+
+            let neighbor_light = access.light(neighbor).unwrap();
+            let neighbor_level = spread_level(channel, block_face.direction, level);
+
+            if neighbor_light.get(channel) < neighbor_level {
+                access.set_light(neighbor, neighbor_light.with(channel, neighbor_level));
+                queue.push_back((neighbor, channel));
+            }
+        }
+    }
+}
+
+/// Take away light spreading outwards from every cell in the removal queue,
+/// which holds the level each cell had before it was cleared.
+///
+/// Returns the cells lit from elsewhere which bordered the removed light, and
+/// should be propagated to fill it in again.
+fn remove<A: LightAccess>(
+    access: &mut A,
+    registry: &BlockRegistry,
+    mut removal: VecDeque<(BlockPos, Channel, u8)>,
+) -> VecDeque<(BlockPos, Channel)> {
+    let mut queue = VecDeque::new();
+
+    while let Some((position, channel, level)) = removal.pop_front() {
+        for block_face in &BLOCK_FACES {
+            let neighbor = position + block_face.normal;
+            let neighbor_light = match access.light(neighbor) {
+                Some(light) => light,
+                None => continue,
+            };
+
+            let neighbor_level = neighbor_light.get(channel);
+            if neighbor_level == 0 {
+                continue;
+            }
+
+            // light which came from the removed cell goes too
+            if spread_level(channel, block_face.direction, level) >= neighbor_level {
+                access.set_light(neighbor, neighbor_light.with(channel, 0));
+                removal.push_back((neighbor, channel, neighbor_level));
+
+                // light sources keep their own light
+                if channel != Channel::Sky && emit(access, registry, neighbor, channel) {
+                    queue.push_back((neighbor, channel));
+                }
+            } else {
+                queue.push_back((neighbor, channel));
+            }
+        }
+    }
+
+    queue
+}
+
+/// Positions of the cells on the face of a chunk in the given direction,
+/// relative to the chunk origin.
+fn border_cells(direction: Direction) -> impl Iterator<Item = Position> {
+    let (axis, layer) = match direction {
+        Direction::West => (0, 0),
+        Direction::East => (0, CHUNK_SIZE - 1),
+        Direction::South => (1, 0),
+        Direction::North => (1, CHUNK_SIZE - 1),
+        Direction::Bottom => (2, 0),
+        Direction::Top => (2, CHUNK_SIZE - 1),
+    };
+
+    (0..CHUNK_SIZE * CHUNK_SIZE).map(move |i| {
+        let mut cell = Position::new(0, 0, 0);
+        cell.set(axis, layer);
+        cell.set((axis + 1) % 3, i % CHUNK_SIZE);
+        cell.set((axis + 2) % 3, i / CHUNK_SIZE);
+        cell
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::block::AIR;
+
+    fn registry() -> BlockRegistry {
+        include_str!("../../../assets/blocks.ron").parse().unwrap()
+    }
+
+    /// A single chunk at the origin, with nothing loaded around it.
+    struct Scene {
+        block_data: ChunkData<Block>,
+        light_data: ChunkData<LightLevel>,
+    }
+
+    impl Scene {
+        fn new(registry: &BlockRegistry, build: impl Fn(&mut ChunkData<Block>)) -> Scene {
+            let mut block_data = ChunkData::new(AIR);
+            build(&mut block_data);
+
+            let mut light_data = ChunkData::new(LightLevel::default());
+            light_chunk(registry, ChunkPos::new(0, 0, 0), &block_data, &mut light_data);
+
+            Scene { block_data, light_data }
+        }
+
+        fn light(&self, x: i64, y: i64, z: i64) -> LightLevel {
+            self.light_data[LocalPos::new(x, y, z)]
+        }
+
+        /// Change a block and update the light around it, as the world does.
+        fn set_block(&mut self, registry: &BlockRegistry, x: i64, y: i64, z: i64, block: Block) {
+            self.block_data.set(LocalPos::new(x, y, z), block);
+            update_block(self, registry, BlockPos::new(x, y, z));
+        }
+
+        /// Check the light matches lighting the blocks again from scratch.
+        fn assert_relit(&self, registry: &BlockRegistry) {
+            let mut expected = ChunkData::new(LightLevel::default());
+            light_chunk(registry, ChunkPos::new(0, 0, 0), &self.block_data, &mut expected);
+
+            for position in LocalPos::all() {
+                assert_eq!(self.light_data[position], expected[position], "at {:?}", position);
+            }
+        }
+    }
+
+    impl LightAccess for Scene {
+        fn block(&self, position: BlockPos) -> Option<Block> {
+            local_pos(position).map(|position| self.block_data[position])
+        }
+
+        fn light(&self, position: BlockPos) -> Option<LightLevel> {
+            local_pos(position).map(|position| self.light_data[position])
+        }
+
+        fn set_light(&mut self, position: BlockPos, light: LightLevel) {
+            if let Some(position) = local_pos(position) {
+                self.light_data.set(position, light);
+            }
+        }
+    }
+
+    fn local_pos(position: BlockPos) -> Option<LocalPos> {
+        match position.split() {
+            (chunk_position, local_position) if chunk_position == ChunkPos::new(0, 0, 0) => Some(local_position),
+            _ => None,
+        }
+    }
+
+    /// A stone floor, with a stone roof over the half of the chunk with x
+    /// below 8.
+    fn overhang(registry: &BlockRegistry) -> Scene {
+        let stone = registry.require("stone").unwrap();
+
+        Scene::new(registry, |block_data| {
+            for x in 0..CHUNK_SIZE {
+                for y in 0..CHUNK_SIZE {
+                    block_data.set(LocalPos::new(x, y, 0), stone);
+                    if x < 8 {
+                        block_data.set(LocalPos::new(x, y, 8), stone);
+                    }
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn sky_light_spreads_under_overhang() {
+        let registry = registry();
+        let scene = overhang(&registry);
+
+        // open to the sky
+        assert_eq!(scene.light(8, 5, 1).sky(), SUNLIGHT);
+        assert_eq!(scene.light(3, 5, 9).sky(), SUNLIGHT);
+        // fading by one for each block under the roof
+        for x in 0..8 {
+            assert_eq!(scene.light(x, 5, 4).sky(), SUNLIGHT - (8 - x as u8));
+        }
+        // solid blocks are never lit
+        assert_eq!(scene.light(3, 5, 8), LightLevel::default());
+        assert_eq!(scene.light(3, 5, 0), LightLevel::default());
+    }
+
+    #[test]
+    fn colored_light_falls_off_per_channel() {
+        let registry = registry();
+        let lamp = registry.require("red_lamp").unwrap();
+        let LightColor(red, green, blue) = registry.get(lamp).light_emission;
+
+        let scene = Scene::new(&registry, |block_data| block_data.set(LocalPos::new(8, 8, 8), lamp));
+
+        for &(x, y, z) in &[(8, 8, 8), (9, 8, 8), (12, 8, 8), (8, 3, 8), (10, 9, 7), (15, 15, 15), (0, 8, 8)] {
+            let distance = ((x - 8i64).abs() + (y - 8i64).abs() + (z - 8i64).abs()) as u8;
+            let light = scene.light(x, y, z);
+
+            assert_eq!(light.block(), LightColor(
+                red.saturating_sub(distance),
+                green.saturating_sub(distance),
+                blue.saturating_sub(distance),
+            ), "at {:?}", (x, y, z));
+        }
+    }
+
+    #[test]
+    fn breaking_blocks_removes_and_restores_light() {
+        let registry = registry();
+        let lamp = registry.require("blue_lamp").unwrap();
+        let mut scene = overhang(&registry);
+
+        // a lamp under the roof, then taken away again
+        scene.set_block(&registry, 4, 4, 4, lamp);
+        assert_eq!(scene.light(4, 6, 4).block(), LightColor(1, 3, 13));
+        scene.assert_relit(&registry);
+
+        scene.set_block(&registry, 4, 4, 4, AIR);
+        assert!(scene.light(4, 6, 4).block().is_dark());
+        scene.assert_relit(&registry);
+
+        // a hole in the roof lets sunlight straight down
+        scene.set_block(&registry, 3, 5, 8, AIR);
+        assert_eq!(scene.light(3, 5, 1).sky(), SUNLIGHT);
+        scene.assert_relit(&registry);
+    }
+
+    #[test]
+    fn placing_blocks_casts_shadows() {
+        let registry = registry();
+        let stone = registry.require("stone").unwrap();
+        let mut scene = overhang(&registry);
+
+        scene.set_block(&registry, 12, 12, 10, stone);
+        assert_eq!(scene.light(12, 12, 9).sky(), SUNLIGHT - 1);
+        assert_eq!(scene.light(12, 12, 1).sky(), SUNLIGHT - 1);
+        scene.assert_relit(&registry);
+
+        // closing off the open side leaves the space under the roof dark
+        for y in 0..CHUNK_SIZE {
+            for z in 1..8 {
+                scene.set_block(&registry, 8, y, z, stone);
+            }
+        }
+        assert_eq!(scene.light(4, 4, 4), LightLevel::default());
+        scene.assert_relit(&registry);
+    }
+}
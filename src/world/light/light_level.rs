@@ -0,0 +1,95 @@
+use serde::Deserialize;
+
+/// Brightest light level of a single channel.
+pub const MAX_LIGHT: u8 = 15;
+
+/// Sky light level of cells open to the sky.
+pub const SUNLIGHT: u8 = MAX_LIGHT;
+
+/// One of the independent channels of light in a cell.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Channel {
+    /// Light coming from the sky, which travels straight down without fading.
+    Sky,
+    /// Channels of the light emitted by blocks.
+    Red,
+    Green,
+    Blue,
+}
+
+impl Channel {
+    pub const ALL: [Channel; 4] = [Channel::Sky, Channel::Red, Channel::Green, Channel::Blue];
+    pub const BLOCK: [Channel; 3] = [Channel::Red, Channel::Green, Channel::Blue];
+
+    /// Position of the channel's four bits in a `LightLevel`.
+    fn shift(&self) -> u16 {
+        match self {
+            Channel::Sky => 0,
+            Channel::Red => 4,
+            Channel::Green => 8,
+            Channel::Blue => 12,
+        }
+    }
+}
+
+/// Colour of block light, with each channel from 0 up to `MAX_LIGHT`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, Deserialize)]
+pub struct LightColor(pub u8, pub u8, pub u8);
+
+impl LightColor {
+    /// Level of a block light channel. The sky channel is always dark.
+    pub fn get(&self, channel: Channel) -> u8 {
+        match channel {
+            Channel::Sky => 0,
+            Channel::Red => self.0,
+            Channel::Green => self.1,
+            Channel::Blue => self.2,
+        }
+    }
+
+    pub fn is_dark(&self) -> bool {
+        *self == LightColor::default()
+    }
+}
+
+/// Light in a cell, with the sky light and the red, green and blue block light
+/// packed into four bits each.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct LightLevel(u16);
+
+impl LightLevel {
+    pub fn new(sky: u8, block: LightColor) -> LightLevel {
+        Channel::BLOCK.iter()
+            .fold(LightLevel::default().with(Channel::Sky, sky), |light, &channel| {
+                light.with(channel, block.get(channel))
+            })
+    }
+
+    pub fn sky(&self) -> u8 {
+        self.get(Channel::Sky)
+    }
+
+    pub fn block(&self) -> LightColor {
+        LightColor(self.get(Channel::Red), self.get(Channel::Green), self.get(Channel::Blue))
+    }
+
+    pub fn get(&self, channel: Channel) -> u8 {
+        (self.0 >> channel.shift() & 0xf) as u8
+    }
+
+    pub fn with(self, channel: Channel, level: u8) -> LightLevel {
+        debug_assert!(level <= MAX_LIGHT, "light level {} out of range", level);
+
+        let shift = channel.shift();
+        LightLevel((self.0 & !(0xf << shift)) | (level as u16) << shift)
+    }
+
+    /// The packed representation, as passed to shaders and stored on disk.
+    pub fn to_raw(self) -> u16 {
+        self.0
+    }
+
+    pub fn from_raw(raw: u16) -> LightLevel {
+        LightLevel(raw)
+    }
+}
@@ -0,0 +1,5 @@
+pub use light_engine::{LightAccess, join_chunk, light_chunk, update_block};
+pub use light_level::{Channel, LightColor, LightLevel, MAX_LIGHT, SUNLIGHT};
+
+mod light_engine;
+mod light_level;
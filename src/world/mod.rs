@@ -1,13 +1,26 @@
-pub use chunk::Chunk;
+pub use block_access::BlockAccess;
+pub use block_outline::BlockOutline;
+pub use chunk::{Chunk, ChunkData, ChunkMeshBuilder, MeshingMode, MeshOptions, PaddedChunkData};
+pub use clock::WorldClock;
 pub use direction::Direction;
-pub use position::Position;
+pub use position::{BlockPos, ChunkPos, LocalPos, Position};
+pub use raycast::{raycast, Ray, RaycastHit};
+pub use streaming::{ChunkStreamer, StreamingPlan};
 pub use world::World;
 
 pub mod block;
+mod block_access;
+mod block_outline;
 mod chunk;
+mod clock;
 mod direction;
+pub mod generator;
 mod light;
 mod position;
+mod raycast;
+pub mod storage;
+mod streaming;
+mod worker;
 mod world;
 
 pub const CHUNK_SIZE: i64 = 16;
@@ -1,9 +1,14 @@
-use std::ops::Add;
+use std::convert::TryFrom;
+use std::ops::{Add, Sub};
+
+use nalgebra as na;
 
 use crate::render_gl::data::f32_f32_f32;
-use crate::world::CHUNK_SIZE;
+use crate::world::{CHUNK_SIZE, CHUNK_VOLUME};
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+/// A general purpose integer vector, used for offsets such as face normals and
+/// vertex positions.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Position {
     pub x: i64,
     pub y: i64,
@@ -26,6 +31,26 @@ impl Position {
             z: self.z + other.z,
         }
     }
+
+    /// Get a component by axis index, where X is 0, Y is 1 and Z is 2.
+    pub fn get(&self, axis: usize) -> i64 {
+        match axis {
+            0 => self.x,
+            1 => self.y,
+            2 => self.z,
+            _ => panic!("invalid axis {}", axis),
+        }
+    }
+
+    /// Set a component by axis index, where X is 0, Y is 1 and Z is 2.
+    pub fn set(&mut self, axis: usize, value: i64) {
+        match axis {
+            0 => self.x = value,
+            1 => self.y = value,
+            2 => self.z = value,
+            _ => panic!("invalid axis {}", axis),
+        }
+    }
 }
 
 impl From<Position> for f32_f32_f32 {
@@ -46,16 +71,18 @@ impl From<(i64, i64, i64)> for Position {
     }
 }
 
+/// Convert an index into chunk data back into the position local to the chunk.
 impl From<i64> for Position {
     fn from(index: i64) -> Self {
         Position {
             x: index % CHUNK_SIZE,
-            y: index / CHUNK_SIZE,
+            y: (index / CHUNK_SIZE) % CHUNK_SIZE,
             z: index / (CHUNK_SIZE * CHUNK_SIZE),
         }
     }
 }
 
+/// Convert a position local to a chunk into an index into the chunk data.
 impl From<&Position> for i64 {
     fn from(position: &Position) -> Self {
         position.x
@@ -75,3 +102,296 @@ impl Add for Position {
         }
     }
 }
+
+impl Sub for Position {
+    type Output = Position;
+
+    fn sub(self, other: Position) -> Self::Output {
+        Position {
+            x: self.x - other.x,
+            y: self.y - other.y,
+            z: self.z - other.z,
+        }
+    }
+}
+
+/// Coordinates of a chunk, in units of `CHUNK_SIZE` blocks.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ChunkPos {
+    pub x: i64,
+    pub y: i64,
+    pub z: i64,
+}
+
+impl ChunkPos {
+    pub const fn new(x: i64, y: i64, z: i64) -> ChunkPos {
+        ChunkPos {
+            x,
+            y,
+            z,
+        }
+    }
+
+    /// World position of the block in the lowest corner of the chunk.
+    pub const fn origin(&self) -> BlockPos {
+        BlockPos::new(
+            self.x * CHUNK_SIZE,
+            self.y * CHUNK_SIZE,
+            self.z * CHUNK_SIZE,
+        )
+    }
+
+    /// World position of a block inside this chunk.
+    pub fn block_pos(&self, local: LocalPos) -> BlockPos {
+        let origin = self.origin();
+
+        BlockPos::new(
+            origin.x + local.x,
+            origin.y + local.y,
+            origin.z + local.z,
+        )
+    }
+}
+
+impl From<(i64, i64, i64)> for ChunkPos {
+    fn from(other: (i64, i64, i64)) -> Self {
+        ChunkPos::new(other.0, other.1, other.2)
+    }
+}
+
+impl Add<Position> for ChunkPos {
+    type Output = ChunkPos;
+
+    fn add(self, offset: Position) -> Self::Output {
+        ChunkPos::new(self.x + offset.x, self.y + offset.y, self.z + offset.z)
+    }
+}
+
+/// World coordinates of a block.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BlockPos {
+    pub x: i64,
+    pub y: i64,
+    pub z: i64,
+}
+
+impl BlockPos {
+    pub const fn new(x: i64, y: i64, z: i64) -> BlockPos {
+        BlockPos {
+            x,
+            y,
+            z,
+        }
+    }
+
+    /// Coordinates of the chunk containing this block.
+    ///
+    /// Uses floor division, so block `-1` lies in chunk `-1` rather than `0`.
+    pub fn chunk_pos(&self) -> ChunkPos {
+        ChunkPos::new(
+            self.x.div_euclid(CHUNK_SIZE),
+            self.y.div_euclid(CHUNK_SIZE),
+            self.z.div_euclid(CHUNK_SIZE),
+        )
+    }
+
+    /// Position of this block relative to the origin of its chunk.
+    pub fn local_pos(&self) -> LocalPos {
+        LocalPos {
+            x: self.x.rem_euclid(CHUNK_SIZE),
+            y: self.y.rem_euclid(CHUNK_SIZE),
+            z: self.z.rem_euclid(CHUNK_SIZE),
+        }
+    }
+
+    /// Split into the chunk containing this block and the position inside it.
+    pub fn split(&self) -> (ChunkPos, LocalPos) {
+        (self.chunk_pos(), self.local_pos())
+    }
+}
+
+impl From<(i64, i64, i64)> for BlockPos {
+    fn from(other: (i64, i64, i64)) -> Self {
+        BlockPos::new(other.0, other.1, other.2)
+    }
+}
+
+/// Find the block containing a point in world space.
+impl From<&na::Point3<f32>> for BlockPos {
+    fn from(point: &na::Point3<f32>) -> Self {
+        BlockPos::new(
+            point.x.floor() as i64,
+            point.y.floor() as i64,
+            point.z.floor() as i64,
+        )
+    }
+}
+
+impl From<BlockPos> for Position {
+    fn from(other: BlockPos) -> Self {
+        Position::new(other.x, other.y, other.z)
+    }
+}
+
+impl Add<Position> for BlockPos {
+    type Output = BlockPos;
+
+    fn add(self, offset: Position) -> Self::Output {
+        BlockPos::new(self.x + offset.x, self.y + offset.y, self.z + offset.z)
+    }
+}
+
+/// Position of a block relative to the origin of its chunk.
+///
+/// Each coordinate is always in the range `0..CHUNK_SIZE`, so it can be used to
+/// index chunk data without bounds checks.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct LocalPos {
+    x: i64,
+    y: i64,
+    z: i64,
+}
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Position {:?} is outside of the chunk", position)]
+    OutOfChunk { position: Position },
+    #[fail(display = "Index {} is outside of the chunk", index)]
+    IndexOutOfChunk { index: i64 },
+}
+
+impl LocalPos {
+    /// Create a local position.
+    ///
+    /// Panics if any coordinate is outside of `0..CHUNK_SIZE`; use
+    /// `LocalPos::try_from` for a checked conversion.
+    pub fn new(x: i64, y: i64, z: i64) -> LocalPos {
+        LocalPos::try_from(Position::new(x, y, z))
+            .expect("local position out of range")
+    }
+
+    pub fn x(&self) -> i64 {
+        self.x
+    }
+
+    pub fn y(&self) -> i64 {
+        self.y
+    }
+
+    pub fn z(&self) -> i64 {
+        self.z
+    }
+
+    /// Index of this position into chunk data.
+    pub fn index(&self) -> usize {
+        i64::from(&Position::from(*self)) as usize
+    }
+
+    /// Iterate over every position in a chunk, in index order.
+    pub fn all() -> impl Iterator<Item = LocalPos> {
+        (0..CHUNK_VOLUME).map(|index| LocalPos::from_position_unchecked(Position::from(index)))
+    }
+
+    const fn from_position_unchecked(position: Position) -> LocalPos {
+        LocalPos {
+            x: position.x,
+            y: position.y,
+            z: position.z,
+        }
+    }
+
+    /// Offset this position, returning `None` if the result lies outside of
+    /// the chunk.
+    pub fn checked_add(&self, offset: &Position) -> Option<LocalPos> {
+        LocalPos::try_from(Position::from(*self) + *offset).ok()
+    }
+}
+
+impl TryFrom<Position> for LocalPos {
+    type Error = Error;
+
+    fn try_from(position: Position) -> Result<Self, Self::Error> {
+        let range = 0..CHUNK_SIZE;
+
+        if range.contains(&position.x) && range.contains(&position.y) && range.contains(&position.z) {
+            Ok(LocalPos::from_position_unchecked(position))
+        } else {
+            Err(Error::OutOfChunk { position })
+        }
+    }
+}
+
+impl TryFrom<i64> for LocalPos {
+    type Error = Error;
+
+    fn try_from(index: i64) -> Result<Self, Self::Error> {
+        if (0..CHUNK_VOLUME).contains(&index) {
+            Ok(LocalPos::from_position_unchecked(Position::from(index)))
+        } else {
+            Err(Error::IndexOutOfChunk { index })
+        }
+    }
+}
+
+impl From<LocalPos> for Position {
+    fn from(other: LocalPos) -> Self {
+        Position::new(other.x, other.y, other.z)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn index_round_trips_for_every_cell() {
+        for index in 0..CHUNK_VOLUME {
+            let position = Position::from(index);
+            assert_eq!(i64::from(&position), index);
+
+            let local = LocalPos::try_from(index).unwrap();
+            assert_eq!(Position::from(local), position);
+            assert_eq!(local.index(), index as usize);
+            assert_eq!(LocalPos::try_from(position).unwrap(), local);
+        }
+    }
+
+    #[test]
+    fn all_visits_every_cell_in_index_order() {
+        let indices: Vec<usize> = LocalPos::all().map(|local| local.index()).collect();
+        let expected: Vec<usize> = (0..CHUNK_VOLUME as usize).collect();
+        assert_eq!(indices, expected);
+    }
+
+    #[test]
+    fn out_of_range_positions_are_rejected() {
+        assert!(LocalPos::try_from(Position::new(-1, 0, 0)).is_err());
+        assert!(LocalPos::try_from(Position::new(0, CHUNK_SIZE, 0)).is_err());
+        assert!(LocalPos::try_from(-1i64).is_err());
+        assert!(LocalPos::try_from(CHUNK_VOLUME).is_err());
+    }
+
+    #[test]
+    fn split_uses_floor_division_for_negative_coordinates() {
+        let (chunk, local) = BlockPos::new(-1, -16, -17).split();
+        assert_eq!(chunk, ChunkPos::new(-1, -1, -2));
+        assert_eq!(local, LocalPos::new(15, 0, 15));
+
+        let (chunk, local) = BlockPos::new(15, 16, 0).split();
+        assert_eq!(chunk, ChunkPos::new(0, 1, 0));
+        assert_eq!(local, LocalPos::new(15, 0, 0));
+    }
+
+    #[test]
+    fn block_pos_round_trips_through_split() {
+        for &x in &[-33, -17, -16, -1, 0, 1, 15, 16, 31, 100] {
+            for &y in &[-16, -5, 0, 7] {
+                for &z in &[-1, 0, 16] {
+                    let position = BlockPos::new(x, y, z);
+                    let (chunk, local) = position.split();
+                    assert_eq!(chunk.block_pos(local), position);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,294 @@
+use nalgebra as na;
+
+use crate::world::{BlockAccess, BlockPos, Direction};
+use crate::world::block::{Block, BlockRegistry};
+
+/// A half line from an origin in a direction, in world space.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Ray {
+    pub origin: na::Point3<f32>,
+    /// Unit length direction.
+    pub direction: na::Vector3<f32>,
+}
+
+impl Ray {
+    /// Create a ray, normalizing its direction. Returns `None` if the
+    /// direction has no length, or the origin or direction are not finite.
+    pub fn new(origin: na::Point3<f32>, direction: na::Vector3<f32>) -> Option<Ray> {
+        if !origin.coords.iter().chain(direction.iter()).all(|value| value.is_finite()) {
+            return None;
+        }
+
+        Some(Ray {
+            origin,
+            direction: direction.try_normalize(f32::EPSILON)?,
+        })
+    }
+
+    /// Get the ray from the camera through a pixel of the screen, by
+    /// unprojecting the pixel onto the near and far planes.
+    ///
+    /// `pixel` is measured from the top left corner of a viewport of the
+    /// given size. Returns `None` if the matrices can not be inverted, or do
+    /// not give a valid ray.
+    pub fn from_screen(
+        pixel: na::Point2<f32>,
+        viewport_size: na::Vector2<f32>,
+        view_matrix: &na::Matrix4<f32>,
+        projection_matrix: &na::Matrix4<f32>,
+    ) -> Option<Ray> {
+        let inverse = (projection_matrix * view_matrix).try_inverse()?;
+
+        let x = pixel.x / viewport_size.x * 2.0 - 1.0;
+        let y = 1.0 - pixel.y / viewport_size.y * 2.0;
+        let near = inverse.transform_point(&na::Point3::new(x, y, -1.0));
+        let far = inverse.transform_point(&na::Point3::new(x, y, 1.0));
+
+        Ray::new(near, far - near)
+    }
+
+    pub fn at(&self, distance: f32) -> na::Point3<f32> {
+        self.origin + self.direction * distance
+    }
+}
+
+/// The first solid block hit by a ray.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RaycastHit {
+    pub position: BlockPos,
+    pub block: Block,
+    /// The face of the block which the ray entered through.
+    pub face: Direction,
+    /// Distance along the ray to the point where it entered the block.
+    pub distance: f32,
+}
+
+/// Find the first solid block along a ray, within `max_distance` of its
+/// origin.
+///
+/// The ray steps through every cell it passes through in order, so it can
+/// not skip over blocks at chunk boundaries or corners. Blocks which do not
+/// fill their cell, like slabs, are only hit where the ray crosses their
+/// bounds. The search stops at parts of the world which are not available.
+pub fn raycast<A: BlockAccess>(
+    access: &A,
+    registry: &BlockRegistry,
+    ray: &Ray,
+    max_distance: f32,
+) -> Option<RaycastHit> {
+    let origin = [ray.origin.x, ray.origin.y, ray.origin.z];
+    let direction = [ray.direction.x, ray.direction.y, ray.direction.z];
+
+    let mut cell = [0; 3];
+    let mut step = [0; 3];
+    // distance along the ray to the next cell boundary on each axis
+    let mut boundary = [f32::INFINITY; 3];
+    // distance along the ray between cell boundaries on each axis
+    let mut spacing = [f32::INFINITY; 3];
+
+    for axis in 0..3 {
+        cell[axis] = origin[axis].floor() as i64;
+
+        if direction[axis] > 0.0 {
+            step[axis] = 1;
+            spacing[axis] = 1.0 / direction[axis];
+            boundary[axis] = (cell[axis] as f32 + 1.0 - origin[axis]) * spacing[axis];
+        } else if direction[axis] < 0.0 {
+            step[axis] = -1;
+            spacing[axis] = -1.0 / direction[axis];
+            boundary[axis] = (origin[axis] - cell[axis] as f32) * spacing[axis];
+        }
+    }
+
+    loop {
+        let position = BlockPos::new(cell[0], cell[1], cell[2]);
+        let block = access.get_block(position)?;
+        let block_type = registry.get(block);
+
+        if block_type.solid {
+            let bounds = block_type.bounds(block.state());
+            let min = na::Point3::new(cell[0] as f32, cell[1] as f32, cell[2] as f32) + bounds.min.coords;
+            let max = na::Point3::new(cell[0] as f32, cell[1] as f32, cell[2] as f32) + bounds.max.coords;
+
+            if let Some((distance, face)) = intersect_box(ray, &min, &max) {
+                if distance <= max_distance {
+                    return Some(RaycastHit { position, block, face, distance });
+                }
+            }
+        }
+
+        // move into the neighbouring cell whose boundary is closest
+        let axis = (0..3)
+            .min_by(|&a, &b| boundary[a].total_cmp(&boundary[b]))
+            .unwrap();
+        if boundary[axis] > max_distance {
+            return None;
+        }
+
+        cell[axis] += step[axis];
+        boundary[axis] += spacing[axis];
+    }
+}
+
+/// Find where a ray enters a box, returning the distance along the ray and
+/// the face it enters through. A ray starting inside the box hits it at a
+/// distance of zero, on the face behind the origin.
+fn intersect_box(ray: &Ray, min: &na::Point3<f32>, max: &na::Point3<f32>) -> Option<(f32, Direction)> {
+    let mut enter = (f32::NEG_INFINITY, Direction::Bottom);
+    let mut exit = f32::INFINITY;
+
+    for axis in 0..3 {
+        let origin = ray.origin[axis];
+        let direction = ray.direction[axis];
+
+        if direction == 0.0 {
+            // parallel to the faces on this axis
+            if origin < min[axis] || origin > max[axis] {
+                return None;
+            }
+            continue;
+        }
+
+        let (near, far, face) = if direction > 0.0 {
+            ((min[axis] - origin) / direction, (max[axis] - origin) / direction, entered_face(axis, 1))
+        } else {
+            ((max[axis] - origin) / direction, (min[axis] - origin) / direction, entered_face(axis, -1))
+        };
+
+        if near > enter.0 {
+            enter = (near, face);
+        }
+        exit = exit.min(far);
+    }
+
+    if enter.0 > exit || exit < 0.0 {
+        return None;
+    }
+
+    Some((enter.0.max(0.0), enter.1))
+}
+
+/// The face of a block entered by a ray moving along an axis in the
+/// direction of `step`.
+fn entered_face(axis: usize, step: i64) -> Direction {
+    match (axis, step > 0) {
+        (0, true) => Direction::West,
+        (0, false) => Direction::East,
+        (1, true) => Direction::South,
+        (1, false) => Direction::North,
+        (_, true) => Direction::Bottom,
+        (_, false) => Direction::Top,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::world::block::AIR;
+
+    fn registry() -> BlockRegistry {
+        include_str!("../../assets/blocks.ron").parse().unwrap()
+    }
+
+    /// Blocks in a small area around the origin, with air wherever no block
+    /// was placed. Further away the world is not available.
+    struct Blocks(HashMap<BlockPos, Block>);
+
+    impl BlockAccess for Blocks {
+        fn get_block(&self, position: BlockPos) -> Option<Block> {
+            if [position.x, position.y, position.z].iter().all(|coordinate| coordinate.abs() <= 16) {
+                Some(self.0.get(&position).cloned().unwrap_or(AIR))
+            } else {
+                None
+            }
+        }
+
+        fn set_block(&mut self, position: BlockPos, block: Block) -> bool {
+            self.0.insert(position, block);
+            true
+        }
+    }
+
+    fn stone_at(registry: &BlockRegistry, positions: &[(i64, i64, i64)]) -> Blocks {
+        let stone = registry.require("stone").unwrap();
+        Blocks(positions.iter().map(|&position| (BlockPos::from(position), stone)).collect())
+    }
+
+    fn ray(origin: (f32, f32, f32), direction: (f32, f32, f32)) -> Ray {
+        Ray::new(
+            na::Point3::new(origin.0, origin.1, origin.2),
+            na::Vector3::new(direction.0, direction.1, direction.2),
+        ).unwrap()
+    }
+
+    fn assert_hit(hit: Option<RaycastHit>, position: (i64, i64, i64), face: Direction, distance: f32) {
+        let hit = hit.expect("ray missed");
+        assert_eq!(hit.position, BlockPos::from(position));
+        assert_eq!(hit.face, face);
+        assert!((hit.distance - distance).abs() < 1e-4, "distance {}", hit.distance);
+    }
+
+    #[test]
+    fn invalid_rays_are_rejected() {
+        let origin = na::Point3::new(0.5, 0.5, 0.5);
+        assert!(Ray::new(origin, na::Vector3::zeros()).is_none());
+        assert!(Ray::new(origin, na::Vector3::new(f32::NAN, 1.0, 0.0)).is_none());
+        assert!(Ray::new(na::Point3::new(f32::INFINITY, 0.0, 0.0), na::Vector3::x()).is_none());
+    }
+
+    #[test]
+    fn axis_aligned_ray_hits_first_block() {
+        let registry = registry();
+        let blocks = stone_at(&registry, &[(5, 0, 0), (7, 0, 0)]);
+
+        let hit = raycast(&blocks, &registry, &ray((0.5, 0.5, 0.5), (1.0, 0.0, 0.0)), 32.0);
+        assert_hit(hit, (5, 0, 0), Direction::West, 4.5);
+    }
+
+    #[test]
+    fn diagonal_ray_visits_every_crossed_cell() {
+        let registry = registry();
+        // the ray passes over (2, 0, 0), and enters (2, 1, 0) from the west
+        let blocks = stone_at(&registry, &[(2, 0, 0), (2, 1, 0)]);
+
+        let hit = raycast(&blocks, &registry, &ray((0.5, 0.5, 0.5), (1.0, 0.5, 0.0)), 32.0);
+        assert_hit(hit, (2, 1, 0), Direction::West, 1.5 * 1.25f32.sqrt());
+    }
+
+    #[test]
+    fn negative_ray_hits_top_face() {
+        let registry = registry();
+        let blocks = stone_at(&registry, &[(0, 0, 1), (0, 0, -3)]);
+
+        let hit = raycast(&blocks, &registry, &ray((0.5, 0.5, 5.5), (0.0, 0.0, -1.0)), 32.0);
+        assert_hit(hit, (0, 0, 1), Direction::Top, 3.5);
+
+        let hit = raycast(&blocks, &registry, &ray((-0.5, 0.5, 0.5), (0.0, 0.0, -1.0)), 32.0);
+        assert!(hit.is_none());
+    }
+
+    #[test]
+    fn ray_starting_inside_block_hits_it_immediately() {
+        let registry = registry();
+        let blocks = stone_at(&registry, &[(0, 0, 0), (1, 0, 0)]);
+
+        let hit = raycast(&blocks, &registry, &ray((0.5, 0.5, 0.5), (1.0, 0.0, 0.0)), 32.0);
+        assert_hit(hit, (0, 0, 0), Direction::West, 0.0);
+    }
+
+    #[test]
+    fn blocks_beyond_max_distance_are_missed() {
+        let registry = registry();
+        let blocks = stone_at(&registry, &[(10, 0, 0)]);
+        let ray = ray((0.5, 0.5, 0.5), (1.0, 0.0, 0.0));
+
+        assert!(raycast(&blocks, &registry, &ray, 5.0).is_none());
+        assert_hit(raycast(&blocks, &registry, &ray, 9.5), (10, 0, 0), Direction::West, 9.5);
+
+        // and the search stops where the world is not available
+        let empty = Blocks(HashMap::new());
+        assert!(raycast(&empty, &registry, &ray, 1000.0).is_none());
+    }
+}
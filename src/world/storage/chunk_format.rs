@@ -0,0 +1,410 @@
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::str;
+
+use crate::world::{CHUNK_VOLUME, ChunkData, LocalPos};
+use crate::world::block::{AIR, Block, BlockRegistry};
+use crate::world::chunk::bits_for;
+
+/// Version of the format written by `serialize_chunk`.
+pub const FORMAT_VERSION: u16 = 2;
+
+const MAGIC: [u8; 4] = *b"GCHK";
+/// Size of the header: the magic, the version and the checksum of the body.
+const HEADER_SIZE: usize = 4 + 2 + 4;
+/// Size of a version 1 chunk, which has no header and stores every block as
+/// its raw 16 bit value.
+const LEGACY_SIZE: usize = CHUNK_VOLUME as usize * 2;
+
+const WORD_BITS: usize = 64;
+
+#[derive(Debug, Fail)]
+pub enum FormatError {
+    #[fail(display = "Data is not a chunk")]
+    UnknownFormat,
+    #[fail(display = "Chunk format version {} is not supported", version)]
+    UnsupportedVersion { version: u16 },
+    #[fail(display = "Chunk checksum {:08x} does not match {:08x}", actual, expected)]
+    ChecksumMismatch { expected: u32, actual: u32 },
+    #[fail(display = "Chunk data ends unexpectedly")]
+    Truncated,
+    #[fail(display = "Chunk has {} bytes of unexpected data at the end", len)]
+    TrailingData { len: usize },
+    #[fail(display = "Chunk palette has {} entries", len)]
+    InvalidPaletteLength { len: usize },
+    #[fail(display = "Chunk palette indices can not be {} bits wide", bits)]
+    InvalidIndexBits { bits: u8 },
+    #[fail(display = "Chunk palette index {} is out of range", index)]
+    InvalidPaletteIndex { index: usize },
+    #[fail(display = "Chunk block name is not valid UTF-8")]
+    InvalidBlockName,
+    #[fail(display = "Chunk contains unknown block {}", name)]
+    UnknownBlock { name: String },
+    #[fail(display = "Chunk contains unknown block id {}", id)]
+    UnknownBlockId { id: u16 },
+    #[fail(display = "Chunk contains block {} with invalid state {}", name, state)]
+    InvalidState { name: String, state: u8 },
+}
+
+/// Upgrades the body of a chunk from one version of the format to the next.
+type Migration = fn(&BlockRegistry, &[u8]) -> Result<Vec<u8>, FormatError>;
+
+/// Migrations from each old version of the format, starting at version 1.
+/// Old chunks are upgraded one version at a time until they reach
+/// `FORMAT_VERSION`.
+const MIGRATIONS: [Migration; FORMAT_VERSION as usize - 1] = [
+    migrate_v1,
+];
+
+/// Encode the blocks of a chunk.
+///
+/// Chunks start with a header holding the format version and a checksum of
+/// the body. The body stores a palette of the distinct blocks in the chunk,
+/// by name so that chunks survive blocks being added to or reordered in the
+/// registry, followed by a packed palette index for every cell.
+pub fn serialize_chunk(registry: &BlockRegistry, block_data: &ChunkData<Block>) -> Vec<u8> {
+    let mut palette = Vec::new();
+    let mut palette_indices = HashMap::new();
+    let indices: Vec<usize> = LocalPos::all()
+        .map(|position| {
+            let block = block_data[position];
+            *palette_indices.entry(block).or_insert_with(|| {
+                palette.push(block);
+                palette.len() - 1
+            })
+        })
+        .collect();
+
+    let mut body = Vec::new();
+    body.extend_from_slice(&(palette.len() as u16).to_le_bytes());
+    for block in &palette {
+        let name = &registry.get(*block).name;
+        body.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        body.extend_from_slice(name.as_bytes());
+        body.push(block.state());
+    }
+
+    let bits = bits_for(palette.len());
+    body.push(bits as u8);
+    // a single block needs no indices
+    if let Some(per_word) = WORD_BITS.checked_div(bits) {
+        for word_indices in indices.chunks(per_word) {
+            let word = word_indices.iter()
+                .enumerate()
+                .fold(0u64, |word, (i, &index)| word | (index as u64) << (i * bits));
+            body.extend_from_slice(&word.to_le_bytes());
+        }
+    }
+
+    let mut bytes = Vec::with_capacity(HEADER_SIZE + body.len());
+    bytes.extend_from_slice(&MAGIC);
+    bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&checksum(&body).to_le_bytes());
+    bytes.extend_from_slice(&body);
+    bytes
+}
+
+/// Decode the blocks of a chunk encoded by `serialize_chunk`, or by any older
+/// version of the format.
+pub fn deserialize_chunk(
+    registry: &BlockRegistry,
+    bytes: &[u8],
+) -> Result<ChunkData<Block>, FormatError> {
+    let (mut version, mut body) = if bytes.starts_with(&MAGIC) {
+        let mut reader = Reader::new(&bytes[MAGIC.len()..]);
+        let version = reader.u16()?;
+        let expected = reader.u32()?;
+        let body = reader.remaining();
+
+        let actual = checksum(body);
+        if actual != expected {
+            return Err(FormatError::ChecksumMismatch { expected, actual });
+        }
+
+        (version, body.to_vec())
+    } else if bytes.len() == LEGACY_SIZE {
+        (1, bytes.to_vec())
+    } else {
+        return Err(FormatError::UnknownFormat);
+    };
+
+    if version == 0 || version > FORMAT_VERSION {
+        return Err(FormatError::UnsupportedVersion { version });
+    }
+
+    while version < FORMAT_VERSION {
+        body = MIGRATIONS[version as usize - 1](registry, &body)?;
+        version += 1;
+    }
+
+    decode_body(registry, &body)
+}
+
+fn decode_body(registry: &BlockRegistry, body: &[u8]) -> Result<ChunkData<Block>, FormatError> {
+    let mut reader = Reader::new(body);
+
+    let palette_len = reader.u16()? as usize;
+    if palette_len == 0 || palette_len > CHUNK_VOLUME as usize {
+        return Err(FormatError::InvalidPaletteLength { len: palette_len });
+    }
+
+    let mut palette = Vec::with_capacity(palette_len);
+    for _ in 0..palette_len {
+        let name_len = reader.u16()? as usize;
+        let name = str::from_utf8(reader.bytes(name_len)?)
+            .map_err(|_| FormatError::InvalidBlockName)?;
+        let state = reader.u8()?;
+
+        let block = registry.id(name)
+            .ok_or_else(|| FormatError::UnknownBlock { name: name.into() })?;
+        if !registry.get(block).is_valid_state(state) {
+            return Err(FormatError::InvalidState { name: name.into(), state });
+        }
+        palette.push(block.with_state(state));
+    }
+
+    let bits = reader.u8()?;
+    if bits as usize != bits_for(palette_len) {
+        return Err(FormatError::InvalidIndexBits { bits });
+    }
+
+    let mut block_data = ChunkData::new(palette[0]);
+    if bits > 0 {
+        let bits = bits as usize;
+        let per_word = WORD_BITS / bits;
+        let mask = (1 << bits) - 1;
+
+        let mut positions = LocalPos::all();
+        for _ in 0..CHUNK_VOLUME as usize / per_word {
+            let word = reader.u64()?;
+            for i in 0..per_word {
+                let index = ((word >> (i * bits)) & mask) as usize;
+                let block = *palette.get(index)
+                    .ok_or(FormatError::InvalidPaletteIndex { index })?;
+                block_data.set(positions.next().unwrap(), block);
+            }
+        }
+    }
+
+    let len = reader.remaining().len();
+    if len > 0 {
+        return Err(FormatError::TrailingData { len });
+    }
+
+    block_data.compact();
+    Ok(block_data)
+}
+
+/// Upgrade a version 1 chunk, a raw block value for every cell, to a palette
+/// of block names.
+fn migrate_v1(registry: &BlockRegistry, body: &[u8]) -> Result<Vec<u8>, FormatError> {
+    if body.len() != LEGACY_SIZE {
+        return Err(FormatError::Truncated);
+    }
+
+    let mut block_data = ChunkData::new(AIR);
+    for (position, raw) in LocalPos::all().zip(body.chunks_exact(2)) {
+        let block = Block::from_raw(u16::from_le_bytes(raw.try_into().unwrap()));
+        if block.id() as usize >= registry.len() {
+            return Err(FormatError::UnknownBlockId { id: block.id() });
+        }
+        block_data.set(position, block);
+    }
+
+    // skip the header written by serialize_chunk
+    Ok(serialize_chunk(registry, &block_data)[HEADER_SIZE..].to_vec())
+}
+
+fn checksum(bytes: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(bytes);
+    hasher.finalize()
+}
+
+/// Reads little endian values from a byte slice, failing instead of
+/// panicking when the slice runs out.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Reader<'a> {
+        Reader { bytes }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], FormatError> {
+        if self.bytes.len() < len {
+            return Err(FormatError::Truncated);
+        }
+
+        let (bytes, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(bytes)
+    }
+
+    fn remaining(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    fn u8(&mut self) -> Result<u8, FormatError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, FormatError> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, FormatError> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, FormatError> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry() -> BlockRegistry {
+        include_str!("../../../assets/blocks.ron").parse().unwrap()
+    }
+
+    /// A chunk using many blocks and states, so the palette indices take
+    /// several bits.
+    fn mixed_chunk(registry: &BlockRegistry) -> ChunkData<Block> {
+        let names = ["stone", "dirt", "grass", "log", "stone_slab", "wheat", "glass"];
+        let blocks: Vec<Block> = names.iter().map(|name| registry.require(name).unwrap()).collect();
+
+        let mut block_data = ChunkData::new(AIR);
+        for position in LocalPos::all() {
+            let index = position.index();
+            if index % 3 == 0 {
+                continue;
+            }
+
+            let block = blocks[index % blocks.len()];
+            let states = (0..=7).filter(|&state| registry.get(block).is_valid_state(state)).count();
+            block_data.set(position, block.with_state((index / 7 % states) as u8));
+        }
+        block_data
+    }
+
+    #[test]
+    fn chunks_round_trip() {
+        let registry = registry();
+
+        let mixed = mixed_chunk(&registry);
+        assert_eq!(deserialize_chunk(&registry, &serialize_chunk(&registry, &mixed)).unwrap(), mixed);
+
+        let uniform = ChunkData::new(registry.require("stone").unwrap());
+        let decoded = deserialize_chunk(&registry, &serialize_chunk(&registry, &uniform)).unwrap();
+        assert_eq!(decoded, uniform);
+        assert!(decoded.is_uniform());
+    }
+
+    #[test]
+    fn version_1_chunks_are_migrated() {
+        let registry = registry();
+        let block_data = mixed_chunk(&registry);
+
+        let legacy: Vec<u8> = LocalPos::all()
+            .flat_map(|position| block_data[position].to_raw().to_le_bytes().to_vec())
+            .collect();
+
+        assert_eq!(deserialize_chunk(&registry, &legacy).unwrap(), block_data);
+    }
+
+    /// Small deterministic xorshift generator, so failures can be reproduced.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn bytes(&mut self, len: usize) -> Vec<u8> {
+            (0..len).map(|_| self.next() as u8).collect()
+        }
+    }
+
+    /// A chunk with a valid header and checksum around the given body.
+    fn with_header(body: &[u8]) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&checksum(body).to_le_bytes());
+        bytes.extend_from_slice(body);
+        bytes
+    }
+
+    #[test]
+    fn random_bytes_do_not_panic() {
+        let registry = registry();
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+
+        for round in 0..2000 {
+            let len = match round % 4 {
+                0 => rng.next() as usize % 64,
+                1 => rng.next() as usize % 4096,
+                // the size of an old chunk, which has no header to check
+                2 => LEGACY_SIZE,
+                _ => HEADER_SIZE + rng.next() as usize % 64,
+            };
+            let mut bytes = rng.bytes(len);
+            if round % 4 == 3 {
+                bytes.splice(..4, MAGIC.iter().cloned());
+            }
+
+            // some of these could be valid old chunks, so only check that
+            // decoding does not panic
+            let _ = deserialize_chunk(&registry, &bytes);
+        }
+    }
+
+    #[test]
+    fn random_bodies_do_not_panic() {
+        let registry = registry();
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        let valid = serialize_chunk(&registry, &mixed_chunk(&registry));
+
+        for round in 0..2000 {
+            // start from a valid body so that decoding gets past the palette
+            let mut body = valid[HEADER_SIZE..].to_vec();
+            match round % 3 {
+                0 => {
+                    let len = rng.next() as usize % 256;
+                    body = rng.bytes(len);
+                }
+                1 => body.truncate(rng.next() as usize % body.len()),
+                _ => {
+                    for _ in 0..1 + rng.next() % 8 {
+                        let index = rng.next() as usize % body.len();
+                        body[index] = rng.next() as u8;
+                    }
+                }
+            }
+
+            let _ = deserialize_chunk(&registry, &with_header(&body));
+        }
+    }
+
+    #[test]
+    fn flipped_bits_and_truncation_are_detected() {
+        let registry = registry();
+        let bytes = serialize_chunk(&registry, &mixed_chunk(&registry));
+
+        for bit in 0..bytes.len() * 8 {
+            let mut corrupt = bytes.clone();
+            corrupt[bit / 8] ^= 1 << (bit % 8);
+            assert!(deserialize_chunk(&registry, &corrupt).is_err(), "bit {} flipped", bit);
+        }
+
+        for len in 0..bytes.len() {
+            assert!(deserialize_chunk(&registry, &bytes[..len]).is_err(), "truncated to {}", len);
+        }
+    }
+}
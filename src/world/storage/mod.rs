@@ -0,0 +1,9 @@
+pub use chunk_format::{deserialize_chunk, FORMAT_VERSION, FormatError, serialize_chunk};
+pub use offline_world::OfflineWorld;
+pub use region::{Region, RegionPos, REGION_SIZE};
+pub use world_storage::{Error, WorldStorage};
+
+mod chunk_format;
+mod offline_world;
+mod region;
+mod world_storage;
@@ -0,0 +1,98 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::world::{BlockAccess, BlockPos, ChunkData, ChunkPos};
+use crate::world::block::{self, Block};
+use crate::world::generator::TerrainGenerator;
+
+use super::{Error, WorldStorage};
+
+/// Blocks of a saved world, edited directly on disk without running the game.
+///
+/// Chunks have to be loaded with `load_area` before their blocks can be
+/// accessed. Chunks which have never been saved are generated, so that edits
+/// to them keep the surrounding terrain. Edits are only written by `save`,
+/// which should not be used while the game has the same world open.
+pub struct OfflineWorld {
+    storage: WorldStorage,
+    generator: Box<dyn TerrainGenerator>,
+    chunks: HashMap<ChunkPos, ChunkData<Block>>,
+    modified: HashSet<ChunkPos>,
+}
+
+impl OfflineWorld {
+    pub fn new(storage: WorldStorage, generator: Box<dyn TerrainGenerator>) -> OfflineWorld {
+        OfflineWorld {
+            storage,
+            generator,
+            chunks: HashMap::new(),
+            modified: HashSet::new(),
+        }
+    }
+
+    /// Load every chunk overlapping the box between two corners, inclusive.
+    pub fn load_area(&mut self, min: BlockPos, max: BlockPos) -> Result<(), Error> {
+        let min = min.chunk_pos();
+        let max = max.chunk_pos();
+
+        for x in min.x.min(max.x)..=min.x.max(max.x) {
+            for y in min.y.min(max.y)..=min.y.max(max.y) {
+                for z in min.z.min(max.z)..=min.z.max(max.z) {
+                    self.load_chunk(ChunkPos::new(x, y, z))?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn load_chunk(&mut self, position: ChunkPos) -> Result<(), Error> {
+        if self.chunks.contains_key(&position) {
+            return Ok(());
+        }
+
+        let block_data = match self.storage.load_chunk(&position)? {
+            Some(block_data) => block_data,
+            None => {
+                let mut block_data = ChunkData::new(block::AIR);
+                self.generator.generate(position, &mut block_data);
+                block_data
+            }
+        };
+        self.chunks.insert(position, block_data);
+
+        Ok(())
+    }
+
+    /// Save every chunk which has been changed.
+    pub fn save(&mut self) -> Result<(), Error> {
+        let modified: Vec<(ChunkPos, &ChunkData<Block>)> = self.modified.iter()
+            .map(|position| (*position, &self.chunks[position]))
+            .collect();
+        self.storage.save_chunks(&modified)?;
+
+        self.modified.clear();
+        Ok(())
+    }
+}
+
+impl BlockAccess for OfflineWorld {
+    fn get_block(&self, position: BlockPos) -> Option<Block> {
+        let (chunk_position, local_position) = position.split();
+
+        self.chunks.get(&chunk_position)
+            .map(|block_data| block_data[local_position])
+    }
+
+    fn set_block(&mut self, position: BlockPos, block: Block) -> bool {
+        let (chunk_position, local_position) = position.split();
+
+        match self.chunks.get_mut(&chunk_position) {
+            Some(block_data) => {
+                block_data.set(local_position, block);
+                self.modified.insert(chunk_position);
+                true
+            }
+            None => false,
+        }
+    }
+}
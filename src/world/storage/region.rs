@@ -0,0 +1,210 @@
+use std::convert::TryInto;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::world::ChunkPos;
+
+use super::Error;
+
+/// Number of chunks along each axis of a region.
+pub const REGION_SIZE: i64 = 8;
+const REGION_VOLUME: usize = (REGION_SIZE * REGION_SIZE * REGION_SIZE) as usize;
+
+const MAGIC: [u8; 4] = *b"GRGN";
+const VERSION: u32 = 1;
+
+/// Size of the header: the magic, the version, and the offset and length of
+/// each chunk's payload.
+const HEADER_SIZE: usize = 4 + 4 + REGION_VOLUME * 8;
+
+/// Coordinates of a region, a cube of `REGION_SIZE` chunks stored in a
+/// single file.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RegionPos {
+    pub x: i64,
+    pub y: i64,
+    pub z: i64,
+}
+
+impl RegionPos {
+    /// Get the region containing a chunk.
+    pub fn of_chunk(position: &ChunkPos) -> RegionPos {
+        RegionPos {
+            x: position.x.div_euclid(REGION_SIZE),
+            y: position.y.div_euclid(REGION_SIZE),
+            z: position.z.div_euclid(REGION_SIZE),
+        }
+    }
+
+    pub fn file_name(&self) -> String {
+        format!("r.{}.{}.{}.region", self.x, self.y, self.z)
+    }
+}
+
+/// Index of a chunk within the offset table of its region.
+fn chunk_index(position: &ChunkPos) -> usize {
+    let x = position.x.rem_euclid(REGION_SIZE);
+    let y = position.y.rem_euclid(REGION_SIZE);
+    let z = position.z.rem_euclid(REGION_SIZE);
+
+    (x + y * REGION_SIZE + z * REGION_SIZE * REGION_SIZE) as usize
+}
+
+/// The payloads of the chunks stored in one region file.
+///
+/// On disk a region starts with a header holding the offset and length of
+/// each chunk's payload in the file, with a length of zero for chunks which
+/// were never saved. Payloads are opaque to the region; they are compressed
+/// chunks encoded by `WorldStorage`.
+pub struct Region {
+    path: PathBuf,
+    payloads: Vec<Option<Vec<u8>>>,
+}
+
+impl Region {
+    /// Read every payload of the region file at `path`, or create an empty
+    /// region if the file does not exist.
+    pub fn open(path: &Path) -> Result<Region, Error> {
+        let mut payloads = vec![None; REGION_VOLUME];
+
+        let mut file = match File::open(path) {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                return Ok(Region { path: path.into(), payloads });
+            }
+            Err(e) => return Err(Error::Io(e)),
+        };
+
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+
+        let table = read_header(path, &bytes)?;
+        for (index, &(offset, length)) in table.iter().enumerate() {
+            if length > 0 {
+                let range = payload_range(path, offset, length, bytes.len() as u64)?;
+                payloads[index] = Some(bytes[range].to_vec());
+            }
+        }
+
+        Ok(Region {
+            path: path.into(),
+            payloads,
+        })
+    }
+
+    /// Read the payload of a single chunk from the region file at `path`
+    /// without reading the rest of the file. Returns `None` if the file does
+    /// not exist or the chunk was never saved.
+    pub fn read_chunk(path: &Path, position: &ChunkPos) -> Result<Option<Vec<u8>>, Error> {
+        let mut file = match File::open(path) {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(Error::Io(e)),
+        };
+        let file_length = file.metadata()?.len();
+
+        let mut header = vec![0; HEADER_SIZE];
+        file.read_exact(&mut header)
+            .map_err(|_| Error::corrupt(path, "header is truncated"))?;
+        let (offset, length) = read_header(path, &header)?[chunk_index(position)];
+
+        if length == 0 {
+            return Ok(None);
+        }
+
+        payload_range(path, offset, length, file_length)?;
+        let mut payload = vec![0; length as usize];
+        file.seek(SeekFrom::Start(offset as u64))?;
+        file.read_exact(&mut payload)?;
+
+        Ok(Some(payload))
+    }
+
+    pub fn get(&self, position: &ChunkPos) -> Option<&[u8]> {
+        self.payloads[chunk_index(position)].as_deref()
+    }
+
+    pub fn set(&mut self, position: &ChunkPos, payload: Vec<u8>) {
+        self.payloads[chunk_index(position)] = Some(payload);
+    }
+
+    /// Remove the payload of a chunk, returning it if the chunk was saved.
+    pub fn remove(&mut self, position: &ChunkPos) -> Option<Vec<u8>> {
+        self.payloads[chunk_index(position)].take()
+    }
+
+    /// Write the region back to its file.
+    ///
+    /// The region is written to a temporary file which then replaces the old
+    /// file, so a crash part way through leaves the old file intact.
+    pub fn save(&self) -> Result<(), Error> {
+        let mut table = Vec::with_capacity(REGION_VOLUME);
+        let mut offset = HEADER_SIZE as u32;
+        for payload in &self.payloads {
+            let length = payload.as_ref().map_or(0, |payload| payload.len() as u32);
+            table.push((offset, length));
+            offset += length;
+        }
+
+        let mut bytes = Vec::with_capacity(offset as usize);
+        bytes.extend_from_slice(&MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        for (offset, length) in table {
+            bytes.extend_from_slice(&offset.to_le_bytes());
+            bytes.extend_from_slice(&length.to_le_bytes());
+        }
+        for payload in self.payloads.iter().flatten() {
+            bytes.extend_from_slice(payload);
+        }
+
+        let temp_path = self.path.with_extension("region.tmp");
+        {
+            let mut file = File::create(&temp_path)?;
+            file.write_all(&bytes)?;
+            file.sync_all()?;
+        }
+        fs::rename(&temp_path, &self.path)?;
+
+        Ok(())
+    }
+}
+
+/// Check the magic and version of a region and read its offset table.
+fn read_header(path: &Path, bytes: &[u8]) -> Result<Vec<(u32, u32)>, Error> {
+    if bytes.len() < HEADER_SIZE {
+        return Err(Error::corrupt(path, "header is truncated"));
+    }
+    if bytes[0..4] != MAGIC {
+        return Err(Error::corrupt(path, "not a region file"));
+    }
+
+    let version = read_u32(&bytes[4..8]);
+    if version != VERSION {
+        return Err(Error::corrupt(path, &format!("unsupported region version {}", version)));
+    }
+
+    Ok(bytes[8..HEADER_SIZE]
+        .chunks_exact(8)
+        .map(|entry| (read_u32(&entry[0..4]), read_u32(&entry[4..8])))
+        .collect())
+}
+
+/// Check that a payload lies within the file and after the header.
+fn payload_range(
+    path: &Path,
+    offset: u32,
+    length: u32,
+    file_length: u64,
+) -> Result<std::ops::Range<usize>, Error> {
+    let end = offset as u64 + length as u64;
+    if (offset as usize) < HEADER_SIZE || end > file_length {
+        return Err(Error::corrupt(path, "chunk lies outside of the file"));
+    }
+
+    Ok(offset as usize..end as usize)
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes(bytes.try_into().unwrap())
+}
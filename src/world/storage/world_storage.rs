@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use flate2::Compression;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+
+use crate::world::{ChunkData, ChunkPos};
+use crate::world::block::{Block, BlockRegistry};
+
+use super::chunk_format::{self, FormatError};
+use super::region::{Region, RegionPos};
+
+/// Largest decompressed chunk accepted, well above the size of any valid
+/// chunk, so that a corrupt payload can not exhaust memory.
+const MAX_CHUNK_SIZE: u64 = 1 << 20;
+/// Directory inside the world directory which unreadable chunks and regions
+/// are moved to.
+const CORRUPT_DIRECTORY: &str = "corrupt";
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "I/O error")]
+    Io(#[cause] io::Error),
+    #[fail(display = "Region file {:?} is corrupt: {}", path, reason)]
+    Corrupt { path: PathBuf, reason: String },
+    #[fail(display = "Chunk in region file {:?} is invalid", path)]
+    InvalidChunk { path: PathBuf, #[cause] error: FormatError },
+}
+
+impl Error {
+    pub(super) fn corrupt(path: &Path, reason: &str) -> Error {
+        Error::Corrupt {
+            path: path.into(),
+            reason: reason.into(),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(other: io::Error) -> Self {
+        Error::Io(other)
+    }
+}
+
+/// Saved chunks of a world, stored in region files in a directory.
+///
+/// Chunks can be loaded and saved from several threads at once.
+pub struct WorldStorage {
+    directory: PathBuf,
+    registry: Arc<BlockRegistry>,
+    /// Held while region files are rewritten, so that two saves touching the
+    /// same region do not drop each other's chunks.
+    write_lock: Mutex<()>,
+}
+
+impl WorldStorage {
+    /// Open the world saved in `directory`, creating the directory if it does
+    /// not exist.
+    pub fn open(directory: &Path, registry: Arc<BlockRegistry>) -> Result<WorldStorage, Error> {
+        fs::create_dir_all(directory)?;
+
+        Ok(WorldStorage {
+            directory: directory.into(),
+            registry,
+            write_lock: Mutex::new(()),
+        })
+    }
+
+    /// Load the blocks of a chunk, or `None` if the chunk has never been
+    /// saved.
+    pub fn load_chunk(&self, position: &ChunkPos) -> Result<Option<ChunkData<Block>>, Error> {
+        let path = self.region_path(&RegionPos::of_chunk(position));
+
+        match Region::read_chunk(&path, position)? {
+            Some(payload) => self.decode_chunk(&path, &payload).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Save the blocks of a batch of chunks. Each region file touched by the
+    /// batch is rewritten once.
+    pub fn save_chunks(&self, chunks: &[(ChunkPos, &ChunkData<Block>)]) -> Result<(), Error> {
+        let mut regions: HashMap<RegionPos, Vec<&(ChunkPos, &ChunkData<Block>)>> = HashMap::new();
+        for chunk in chunks {
+            regions.entry(RegionPos::of_chunk(&chunk.0)).or_default().push(chunk);
+        }
+
+        let _lock = self.write_lock.lock().unwrap();
+        for (region_position, chunks) in regions {
+            let mut region = Region::open(&self.region_path(&region_position))?;
+            for (position, block_data) in chunks {
+                region.set(position, self.encode_chunk(block_data)?);
+            }
+            region.save()?;
+        }
+
+        Ok(())
+    }
+
+    /// Move the saved blocks of a chunk which could not be loaded into the
+    /// `corrupt` directory, so that saving the chunk again does not overwrite
+    /// them. If the region file itself can not be read, the whole file is
+    /// moved. Returns the path the data was moved to.
+    pub fn quarantine_chunk(&self, position: &ChunkPos) -> Result<PathBuf, Error> {
+        let _lock = self.write_lock.lock().unwrap();
+
+        let region_position = RegionPos::of_chunk(position);
+        let path = self.region_path(&region_position);
+        let directory = self.directory.join(CORRUPT_DIRECTORY);
+        fs::create_dir_all(&directory)?;
+
+        match Region::open(&path) {
+            Ok(mut region) => {
+                let name = format!("c.{}.{}.{}.chunk", position.x, position.y, position.z);
+                let destination = unused_path(&directory, &name);
+                if let Some(payload) = region.remove(position) {
+                    fs::write(&destination, payload)?;
+                    region.save()?;
+                }
+                Ok(destination)
+            }
+            Err(Error::Io(error)) => Err(Error::Io(error)),
+            Err(_) => {
+                let destination = unused_path(&directory, &region_position.file_name());
+                fs::rename(&path, &destination)?;
+                Ok(destination)
+            }
+        }
+    }
+
+    fn region_path(&self, position: &RegionPos) -> PathBuf {
+        self.directory.join(position.file_name())
+    }
+
+    /// Serialize and compress the blocks of a chunk.
+    fn encode_chunk(&self, block_data: &ChunkData<Block>) -> Result<Vec<u8>, Error> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&chunk_format::serialize_chunk(&self.registry, block_data))?;
+        Ok(encoder.finish()?)
+    }
+
+    fn decode_chunk(&self, path: &Path, payload: &[u8]) -> Result<ChunkData<Block>, Error> {
+        let mut bytes = Vec::new();
+        ZlibDecoder::new(payload)
+            .take(MAX_CHUNK_SIZE)
+            .read_to_end(&mut bytes)
+            .map_err(|_| Error::corrupt(path, "chunk could not be decompressed"))?;
+
+        chunk_format::deserialize_chunk(&self.registry, &bytes)
+            .map_err(|error| Error::InvalidChunk { path: path.into(), error })
+    }
+}
+
+/// A path in `directory` for a file named `name` which does not exist yet,
+/// adding a number to the name if needed.
+fn unused_path(directory: &Path, name: &str) -> PathBuf {
+    let mut path = directory.join(name);
+    let mut copy = 1;
+    while path.exists() {
+        path = directory.join(format!("{}.{}", name, copy));
+        copy += 1;
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::process;
+
+    use super::*;
+    use crate::world::LocalPos;
+    use crate::world::block::AIR;
+
+    fn registry() -> Arc<BlockRegistry> {
+        Arc::new(include_str!("../../../assets/blocks.ron").parse().unwrap())
+    }
+
+    /// An empty directory to save a world in, unique to the test.
+    fn directory(name: &str) -> PathBuf {
+        let directory = env::temp_dir().join(format!("game-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        directory
+    }
+
+    fn chunk(registry: &BlockRegistry, seed: i64) -> ChunkData<Block> {
+        let blocks = [registry.require("stone").unwrap(), registry.require("dirt").unwrap()];
+
+        let mut block_data = ChunkData::new(AIR);
+        for position in LocalPos::all() {
+            if (position.index() as i64 + seed) % 5 < 2 {
+                block_data.set(position, blocks[(position.z() + seed) as usize % 2]);
+            }
+        }
+        block_data
+    }
+
+    #[test]
+    fn saved_chunks_are_loaded() {
+        let registry = registry();
+        let directory = directory("saved-chunks-are-loaded");
+        let storage = WorldStorage::open(&directory, Arc::clone(&registry)).unwrap();
+
+        // in two regions, one at negative coordinates
+        let positions = [ChunkPos::new(0, 0, 0), ChunkPos::new(3, 1, 0), ChunkPos::new(-1, -9, -2)];
+        let chunks: Vec<ChunkData<Block>> = (0..3).map(|seed| chunk(&registry, seed)).collect();
+        let batch: Vec<(ChunkPos, &ChunkData<Block>)> = positions.iter().cloned().zip(&chunks).collect();
+        storage.save_chunks(&batch).unwrap();
+
+        for (position, block_data) in &batch {
+            assert_eq!(storage.load_chunk(position).unwrap().as_ref(), Some(*block_data));
+        }
+        assert!(storage.load_chunk(&ChunkPos::new(1, 0, 0)).unwrap().is_none());
+        assert!(storage.load_chunk(&ChunkPos::new(100, 0, 0)).unwrap().is_none());
+
+        // saving one chunk again keeps the rest of its region
+        let replacement = chunk(&registry, 7);
+        storage.save_chunks(&[(positions[0], &replacement)]).unwrap();
+        assert_eq!(storage.load_chunk(&positions[0]).unwrap(), Some(replacement));
+        assert_eq!(storage.load_chunk(&positions[1]).unwrap().as_ref(), Some(&chunks[1]));
+
+        // and a new storage reads the same files
+        let reopened = WorldStorage::open(&directory, registry).unwrap();
+        assert_eq!(reopened.load_chunk(&positions[2]).unwrap().as_ref(), Some(&chunks[2]));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn unreadable_chunks_are_quarantined() {
+        let registry = registry();
+        let directory = directory("unreadable-chunks-are-quarantined");
+        let storage = WorldStorage::open(&directory, Arc::clone(&registry)).unwrap();
+
+        let corrupt = ChunkPos::new(0, 0, 0);
+        let neighbor = ChunkPos::new(1, 0, 0);
+        let block_data = chunk(&registry, 3);
+        storage.save_chunks(&[(corrupt, &block_data), (neighbor, &block_data)]).unwrap();
+
+        // replace the chunk with a payload which does not decompress
+        let path = storage.region_path(&RegionPos::of_chunk(&corrupt));
+        let mut region = Region::open(&path).unwrap();
+        region.set(&corrupt, b"not a chunk".to_vec());
+        region.save().unwrap();
+        assert!(storage.load_chunk(&corrupt).is_err());
+
+        let moved = storage.quarantine_chunk(&corrupt).unwrap();
+        assert_eq!(fs::read(&moved).unwrap(), b"not a chunk");
+        assert!(storage.load_chunk(&corrupt).unwrap().is_none());
+        assert_eq!(storage.load_chunk(&neighbor).unwrap(), Some(block_data));
+
+        // a region which can not be read at all is moved as a whole
+        fs::write(&path, b"not a region").unwrap();
+        assert!(storage.load_chunk(&neighbor).is_err());
+
+        let moved_region = storage.quarantine_chunk(&neighbor).unwrap();
+        assert_eq!(fs::read(&moved_region).unwrap(), b"not a region");
+        assert!(!path.exists());
+        assert!(storage.load_chunk(&neighbor).unwrap().is_none());
+
+        // moving the same chunk again keeps the earlier copy
+        region = Region::open(&path).unwrap();
+        region.set(&corrupt, b"still not a chunk".to_vec());
+        region.save().unwrap();
+        let moved_again = storage.quarantine_chunk(&corrupt).unwrap();
+        assert_ne!(moved_again, moved);
+        assert_eq!(fs::read(&moved).unwrap(), b"not a chunk");
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,185 @@
+use std::collections::HashSet;
+
+use crate::world::ChunkPos;
+
+/// Decides which chunks should be resident around a viewer.
+///
+/// Chunks are kept loaded within a cylinder around the viewer's chunk:
+/// `view_radius` chunks horizontally and `vertical_radius` chunks above and
+/// below. Chunks are only unloaded once they are `unload_margin` chunks beyond
+/// that, so moving back and forth across a chunk border does not repeatedly
+/// load and unload the same chunks.
+pub struct ChunkStreamer {
+    pub view_radius: i64,
+    pub vertical_radius: i64,
+    pub unload_margin: i64,
+    /// Maximum number of chunks loaded in a single update.
+    pub load_budget: usize,
+}
+
+/// Chunks to load and unload to bring the resident set up to date.
+#[derive(Debug, Default)]
+pub struct StreamingPlan {
+    /// Chunks entering the view radius, nearest first, limited to the load
+    /// budget.
+    pub load: Vec<ChunkPos>,
+    /// Chunks which have left the view radius.
+    pub unload: Vec<ChunkPos>,
+}
+
+impl ChunkStreamer {
+    pub fn new(view_radius: i64, vertical_radius: i64, load_budget: usize) -> ChunkStreamer {
+        ChunkStreamer {
+            view_radius,
+            vertical_radius,
+            unload_margin: 1,
+            load_budget,
+        }
+    }
+
+    /// Determine if a chunk lies within the view radius of the viewer's chunk,
+    /// extended by `margin` chunks in every direction.
+    fn in_range(&self, center: &ChunkPos, position: &ChunkPos, margin: i64) -> bool {
+        let dx = position.x - center.x;
+        let dy = position.y - center.y;
+        let dz = position.z - center.z;
+        let radius = self.view_radius + margin;
+
+        dx * dx + dy * dy <= radius * radius
+            && dz.abs() <= self.vertical_radius + margin
+    }
+
+    /// Determine if a chunk should stay loaded for a viewer in the chunk
+    /// `center`.
+    pub fn should_keep(&self, center: &ChunkPos, position: &ChunkPos) -> bool {
+        self.in_range(center, position, self.unload_margin)
+    }
+
+    /// Plan which chunks to load and unload for a viewer in the chunk
+    /// `center`, given the chunks that are currently resident.
+    pub fn plan<'a, I>(&self, center: ChunkPos, resident: I) -> StreamingPlan
+        where I: IntoIterator<Item = &'a ChunkPos>,
+    {
+        let mut plan = StreamingPlan::default();
+        let mut is_resident = HashSet::new();
+
+        for position in resident {
+            is_resident.insert(*position);
+
+            if !self.should_keep(&center, position) {
+                plan.unload.push(*position);
+            }
+        }
+
+        for dx in -self.view_radius..=self.view_radius {
+            for dy in -self.view_radius..=self.view_radius {
+                for dz in -self.vertical_radius..=self.vertical_radius {
+                    let position = ChunkPos::new(center.x + dx, center.y + dy, center.z + dz);
+
+                    if self.in_range(&center, &position, 0) && !is_resident.contains(&position) {
+                        plan.load.push(position);
+                    }
+                }
+            }
+        }
+
+        plan.load.sort_by_key(|position| {
+            let dx = position.x - center.x;
+            let dy = position.y - center.y;
+            let dz = position.z - center.z;
+
+            dx * dx + dy * dy + dz * dz
+        });
+        plan.load.truncate(self.load_budget);
+
+        plan
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(positions: &[(i64, i64, i64)]) -> HashSet<ChunkPos> {
+        positions.iter().map(|&position| ChunkPos::from(position)).collect()
+    }
+
+    /// Apply a plan to the resident set, as the world does.
+    fn apply(resident: &mut HashSet<ChunkPos>, plan: &StreamingPlan) {
+        for position in &plan.unload {
+            assert!(resident.remove(position));
+        }
+        for position in &plan.load {
+            assert!(resident.insert(*position));
+        }
+    }
+
+    #[test]
+    fn loads_view_radius_from_empty() {
+        let streamer = ChunkStreamer::new(1, 0, 100);
+        let plan = streamer.plan(ChunkPos::new(0, 0, 0), &HashSet::new());
+
+        assert_eq!(
+            plan.load.iter().cloned().collect::<HashSet<_>>(),
+            set(&[(0, 0, 0), (1, 0, 0), (-1, 0, 0), (0, 1, 0), (0, -1, 0)]),
+        );
+        assert_eq!(plan.load[0], ChunkPos::new(0, 0, 0));
+        assert!(plan.unload.is_empty());
+    }
+
+    #[test]
+    fn moving_center_loads_and_unloads_exact_sets() {
+        let streamer = ChunkStreamer::new(1, 0, 100);
+        let mut resident = HashSet::new();
+        let plan = streamer.plan(ChunkPos::new(0, 0, 0), &resident);
+        apply(&mut resident, &plan);
+
+        // a single step stays within the unload margin
+        let plan = streamer.plan(ChunkPos::new(1, 0, 0), &resident);
+        assert_eq!(
+            plan.load.iter().cloned().collect::<HashSet<_>>(),
+            set(&[(2, 0, 0), (1, 1, 0), (1, -1, 0)]),
+        );
+        assert!(plan.unload.is_empty());
+        apply(&mut resident, &plan);
+
+        let plan = streamer.plan(ChunkPos::new(3, 0, 0), &resident);
+        assert_eq!(
+            plan.unload.iter().cloned().collect::<HashSet<_>>(),
+            set(&[(-1, 0, 0), (0, 0, 0), (0, 1, 0), (0, -1, 0), (1, 1, 0), (1, -1, 0)]),
+        );
+        assert_eq!(
+            plan.load.iter().cloned().collect::<HashSet<_>>(),
+            set(&[(3, 0, 0), (4, 0, 0), (3, 1, 0), (3, -1, 0)]),
+        );
+        apply(&mut resident, &plan);
+
+        assert_eq!(
+            resident,
+            set(&[(1, 0, 0), (2, 0, 0), (3, 0, 0), (4, 0, 0), (3, 1, 0), (3, -1, 0)]),
+        );
+    }
+
+    #[test]
+    fn vertical_radius_limits_loaded_layers() {
+        let streamer = ChunkStreamer::new(0, 1, 100);
+        let plan = streamer.plan(ChunkPos::new(2, 2, 2), &HashSet::new());
+
+        assert_eq!(
+            plan.load.iter().cloned().collect::<HashSet<_>>(),
+            set(&[(2, 2, 1), (2, 2, 2), (2, 2, 3)]),
+        );
+    }
+
+    #[test]
+    fn load_budget_takes_nearest_chunks() {
+        let streamer = ChunkStreamer::new(2, 0, 5);
+        let plan = streamer.plan(ChunkPos::new(0, 0, 0), &HashSet::new());
+
+        assert_eq!(plan.load.len(), 5);
+        assert_eq!(
+            plan.load.iter().cloned().collect::<HashSet<_>>(),
+            set(&[(0, 0, 0), (1, 0, 0), (-1, 0, 0), (0, 1, 0), (0, -1, 0)]),
+        );
+    }
+}
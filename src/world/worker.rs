@@ -0,0 +1,326 @@
+use std::collections::VecDeque;
+use std::mem;
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::mpsc::{self, Receiver};
+use std::thread::{self, JoinHandle};
+
+use failure::Fail;
+
+use crate::debug;
+use crate::render_gl::TextureAtlas;
+use crate::world::{ChunkData, ChunkMeshBuilder, ChunkPos, MeshOptions, PaddedChunkData};
+use crate::world::block::{self, Block, BlockRegistry};
+use crate::world::generator::TerrainGenerator;
+use crate::world::light::{self, LightLevel};
+use crate::world::storage::{self, WorldStorage};
+
+/// Work to be done on a worker thread.
+pub enum Job {
+    /// Load the blocks of a chunk from storage, or generate them if the chunk
+    /// has never been saved, then light it.
+    ///
+    /// Saved blocks which can not be read are moved aside with
+    /// `WorldStorage::quarantine_chunk` and the chunk is generated again.
+    Generate {
+        position: ChunkPos,
+    },
+    /// Rebuild the mesh of a loaded chunk from a copy of its data.
+    Mesh {
+        position: ChunkPos,
+        revision: u64,
+        block_data: PaddedChunkData<Block>,
+        light_data: PaddedChunkData<LightLevel>,
+        options: MeshOptions,
+    },
+    /// Save the blocks of unloaded chunks with unsaved changes.
+    Save {
+        chunks: Vec<(ChunkPos, ChunkData<Block>)>,
+    },
+}
+
+/// Result of a finished `Job`, to be applied on the render thread.
+pub enum JobResult {
+    Generated {
+        position: ChunkPos,
+        block_data: ChunkData<Block>,
+        light_data: ChunkData<LightLevel>,
+        /// Why the saved blocks could not be loaded, if the chunk was
+        /// generated again in their place.
+        load_error: Option<failure::Error>,
+    },
+    /// The saved blocks of a chunk could not be loaded, nor moved aside. The
+    /// chunk must not be loaded, or saving it would overwrite them.
+    LoadFailed {
+        position: ChunkPos,
+        error: failure::Error,
+    },
+    Meshed {
+        position: ChunkPos,
+        revision: u64,
+        mesh: ChunkMeshBuilder,
+    },
+    Saved {
+        positions: Vec<ChunkPos>,
+        result: Result<(), storage::Error>,
+    },
+}
+
+/// Jobs waiting for a free worker.
+///
+/// Mesh jobs are queued separately and always taken first, so that the
+/// meshes of edited chunks are rebuilt without waiting behind chunks queued
+/// for generation. Saves are queued in order with the chunks.
+#[derive(Default)]
+struct JobQueue {
+    meshes: VecDeque<Job>,
+    chunks: VecDeque<Job>,
+    /// Set once the pool is dropped, stopping the workers.
+    closed: bool,
+}
+
+impl JobQueue {
+    fn push(&mut self, job: Job) {
+        match job {
+            Job::Mesh { .. } => self.meshes.push_back(job),
+            Job::Generate { .. } | Job::Save { .. } => self.chunks.push_back(job),
+        }
+    }
+
+    fn pop(&mut self) -> Option<Job> {
+        self.meshes.pop_front().or_else(|| self.chunks.pop_front())
+    }
+}
+
+/// The job queue shared between the render thread and the workers, with a
+/// condition variable to wake a worker when a job is submitted.
+#[derive(Default)]
+struct SharedQueue {
+    jobs: Mutex<JobQueue>,
+    available: Condvar,
+}
+
+impl SharedQueue {
+    /// Wait for the next job, or return `None` once the queue is closed.
+    fn next(&self) -> Option<Job> {
+        let mut jobs = self.jobs.lock().unwrap();
+        loop {
+            if jobs.closed {
+                return None;
+            }
+            if let Some(job) = jobs.pop() {
+                return Some(job);
+            }
+            jobs = self.available.wait(jobs).unwrap();
+        }
+    }
+}
+
+/// A pool of threads loading, generating, meshing and saving chunks in the
+/// background.
+///
+/// Jobs are taken from a shared queue by whichever worker is free, and results
+/// are collected with `try_recv` on the render thread, which then uploads the
+/// meshes to the GPU.
+pub struct ChunkWorkers {
+    queue: Arc<SharedQueue>,
+    results: Receiver<JobResult>,
+    threads: Vec<JoinHandle<()>>,
+    /// Used to finish queued saves when the pool is dropped.
+    storage: Arc<WorldStorage>,
+}
+
+impl ChunkWorkers {
+    pub fn new(
+        thread_count: usize,
+        generator: Arc<dyn TerrainGenerator>,
+        storage: Arc<WorldStorage>,
+        registry: Arc<BlockRegistry>,
+        atlas: TextureAtlas,
+    ) -> ChunkWorkers {
+        let queue = Arc::new(SharedQueue::default());
+        let (result_sender, result_receiver) = mpsc::channel();
+
+        let threads = (0..thread_count)
+            .map(|i| {
+                let queue = Arc::clone(&queue);
+                let results = result_sender.clone();
+                let generator = Arc::clone(&generator);
+                let storage = Arc::clone(&storage);
+                let registry = Arc::clone(&registry);
+
+                thread::Builder::new()
+                    .name(format!("chunk-worker-{}", i))
+                    .spawn(move || {
+                        while let Some(job) = queue.next() {
+                            let result = run_job(job, generator.as_ref(), &storage, &registry, atlas);
+                            if results.send(result).is_err() {
+                                break;
+                            }
+                        }
+                    })
+                    .expect("failed to spawn chunk worker")
+            })
+            .collect();
+
+        ChunkWorkers {
+            queue,
+            results: result_receiver,
+            threads,
+            storage,
+        }
+    }
+
+    pub fn submit(&self, job: Job) {
+        self.queue.jobs.lock().unwrap().push(job);
+        self.queue.available.notify_one();
+    }
+
+    /// Take the next finished result, if there is one.
+    pub fn try_recv(&self) -> Option<JobResult> {
+        self.results.try_recv().ok()
+    }
+}
+
+impl Drop for ChunkWorkers {
+    fn drop(&mut self) {
+        // closing the job queue stops the workers once they finish their
+        // current job, leaving any jobs still queued
+        self.queue.jobs.lock().unwrap().closed = true;
+        self.queue.available.notify_all();
+
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+
+        // queued saves are finished here so that unloaded chunks keep their
+        // changes, and the rest of the jobs are discarded. There is no one
+        // left to return an error to, so it is printed.
+        let jobs = mem::take(&mut self.queue.jobs.lock().unwrap().chunks);
+        for job in jobs {
+            if let Job::Save { chunks } = job {
+                let batch: Vec<(ChunkPos, &ChunkData<Block>)> = chunks.iter()
+                    .map(|(position, block_data)| (*position, block_data))
+                    .collect();
+
+                if let Err(error) = self.storage.save_chunks(&batch) {
+                    eprintln!("{}", debug::failure_to_string(error.into()));
+                }
+            }
+        }
+    }
+}
+
+fn run_job(
+    job: Job,
+    generator: &dyn TerrainGenerator,
+    storage: &WorldStorage,
+    registry: &BlockRegistry,
+    atlas: TextureAtlas,
+) -> JobResult {
+    match job {
+        Job::Generate { position } => {
+            let (block_data, load_error) = match storage.load_chunk(&position) {
+                Ok(Some(block_data)) => (block_data, None),
+                Ok(None) => (generate(generator, position), None),
+                Err(error) => match storage.quarantine_chunk(&position) {
+                    Ok(path) => {
+                        let message = format!("Chunk {:?} could not be loaded, so it was moved to {:?} and generated again", position, path);
+                        (generate(generator, position), Some(error.context(message).into()))
+                    }
+                    Err(quarantine_error) => {
+                        let message = format!("Chunk {:?} could not be loaded ({}) or moved aside, so it is left unloaded", position, error);
+                        return JobResult::LoadFailed {
+                            position,
+                            error: quarantine_error.context(message).into(),
+                        };
+                    }
+                },
+            };
+
+            let mut light_data = ChunkData::new(LightLevel::default());
+            light::light_chunk(registry, position, &block_data, &mut light_data);
+
+            JobResult::Generated {
+                position,
+                block_data,
+                light_data,
+                load_error,
+            }
+        }
+        Job::Mesh { position, revision, block_data, light_data, options } => {
+            let mut mesh = ChunkMeshBuilder::new(atlas, options);
+            mesh.build(&position, &block_data, &light_data, registry);
+
+            JobResult::Meshed {
+                position,
+                revision,
+                mesh,
+            }
+        }
+        Job::Save { chunks } => {
+            let batch: Vec<(ChunkPos, &ChunkData<Block>)> = chunks.iter()
+                .map(|(position, block_data)| (*position, block_data))
+                .collect();
+
+            JobResult::Saved {
+                positions: chunks.iter().map(|&(position, _)| position).collect(),
+                result: storage.save_chunks(&batch),
+            }
+        }
+    }
+}
+
+fn generate(generator: &dyn TerrainGenerator, position: ChunkPos) -> ChunkData<Block> {
+    let mut block_data = ChunkData::new(block::AIR);
+    generator.generate(position, &mut block_data);
+    block_data.compact();
+    block_data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generate_job(x: i64) -> Job {
+        Job::Generate { position: ChunkPos::new(x, 0, 0) }
+    }
+
+    fn mesh_job(x: i64) -> Job {
+        Job::Mesh {
+            position: ChunkPos::new(x, 0, 0),
+            revision: 0,
+            block_data: PaddedChunkData::from_chunks(block::AIR, |_| None),
+            light_data: PaddedChunkData::from_chunks(LightLevel::default(), |_| None),
+            options: MeshOptions::default(),
+        }
+    }
+
+    fn save_job(x: i64) -> Job {
+        Job::Save {
+            chunks: vec![(ChunkPos::new(x, 0, 0), ChunkData::new(block::AIR))],
+        }
+    }
+
+    fn position(job: &Job) -> i64 {
+        match job {
+            Job::Generate { position } | Job::Mesh { position, .. } => position.x,
+            Job::Save { chunks } => chunks[0].0.x,
+        }
+    }
+
+    #[test]
+    fn mesh_jobs_are_taken_before_queued_chunks() {
+        let mut queue = JobQueue::default();
+        queue.push(generate_job(0));
+        queue.push(generate_job(1));
+        queue.push(mesh_job(2));
+        queue.push(save_job(3));
+        queue.push(generate_job(4));
+        queue.push(mesh_job(5));
+
+        let order: Vec<i64> = std::iter::from_fn(|| queue.pop())
+            .map(|job| position(&job))
+            .collect();
+        assert_eq!(order, vec![2, 5, 0, 1, 3, 4]);
+    }
+}
@@ -1,51 +1,477 @@
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::collections::hash_map;
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+
 use nalgebra as na;
 
 use crate::render_gl::{Program, Texture};
 use crate::resources::Resources;
 
-use super::Chunk;
+use super::{BlockAccess, BlockPos, CHUNK_SIZE, Chunk, ChunkData, ChunkPos, ChunkStreamer, LocalPos, MeshOptions, PaddedChunkData, Position, Ray, RaycastHit, WorldClock};
+use super::block::{self, Block, BlockRegistry};
+use super::generator::HeightmapGenerator;
+use super::light::{self, LightAccess, LightColor, LightLevel, SUNLIGHT};
+use super::storage::{self, WorldStorage};
+use super::worker::{ChunkWorkers, Job, JobResult};
+
+/// Default number of chunks kept loaded around the viewer horizontally.
+const VIEW_RADIUS: i64 = 6;
+/// Default number of chunks kept loaded above and below the viewer.
+const VERTICAL_RADIUS: i64 = 2;
+/// Default number of chunks queued for generation per frame.
+const LOAD_BUDGET: usize = 8;
+/// Time of day when the world is created: mid morning.
+const START_TIME: f32 = 0.35;
+/// Fraction of the view distance at which fog starts.
+const FOG_START: f32 = 0.6;
+/// Maximum number of chunks waiting to be generated at once. Keeps the queue
+/// short when moving quickly, so the nearest chunks are generated first.
+const MAX_PENDING_CHUNKS: usize = 64;
 
 pub struct World {
-    chunk: Chunk,
+    /// Loaded chunks, keyed by chunk coordinates.
+    chunks: HashMap<ChunkPos, Chunk>,
+    registry: Arc<BlockRegistry>,
+    storage: Arc<WorldStorage>,
+    /// Chunks queued for generation on the worker threads.
+    pending: HashSet<ChunkPos>,
+    /// Unloaded chunks being saved on the worker threads. They are not loaded
+    /// again until the save finishes, so the old blocks are never read back.
+    saving: HashMap<ChunkPos, Chunk>,
+    /// Unloaded chunks which failed to save, kept until they are saved by
+    /// `save` or loaded again.
+    unsaved: HashMap<ChunkPos, Chunk>,
+    /// Chunks whose saved blocks could not be loaded or moved aside. They are
+    /// never loaded, so that saving them can not overwrite the saved blocks.
+    failed: HashSet<ChunkPos>,
+    /// Errors from worker jobs not yet returned by `update`.
+    errors: VecDeque<failure::Error>,
+    workers: ChunkWorkers,
+    /// Last revision given to a chunk mesh rebuild. Shared by every chunk so
+    /// that revisions are unique across unloading and loading.
+    mesh_revision: u64,
+    pub streamer: ChunkStreamer,
+    pub clock: WorldClock,
+    mesh_options: MeshOptions,
     program: Program,
     texture: Texture,
     view_location: i32,
     projection_location: i32,
     tex_face_location: i32,
+    tile_size_location: i32,
+    sky_light_location: i32,
+    camera_pos_location: i32,
+    fog_color_location: i32,
+    fog_range_location: i32,
 }
 
 impl World {
-    pub fn new(res: &Resources, gl: &gl::Gl) -> Result<World, failure::Error> {
+    /// Create a world which is saved in `save_directory`. Chunks which have
+    /// been saved there are loaded instead of being generated from `seed`.
+    pub fn new(
+        res: &Resources,
+        gl: &gl::Gl,
+        seed: u32,
+        save_directory: &Path,
+    ) -> Result<World, failure::Error> {
         // setup shader program
         let program = Program::from_res("shaders/cube", gl, res)?;
         let view_location = program.get_uniform_location("View")?;
         let projection_location = program.get_uniform_location("Projection")?;
         let tex_face_location = program.get_uniform_location("TexFace")?;
+        let tile_size_location = program.get_uniform_location("TileSize")?;
+        let sky_light_location = program.get_uniform_location("SkyLight")?;
+        let camera_pos_location = program.get_uniform_location("CameraPos")?;
+        let fog_color_location = program.get_uniform_location("FogColor")?;
+        let fog_range_location = program.get_uniform_location("FogRange")?;
 
         // load textures
         let texture = Texture::from_res_rgba("textures/minecraft.png")
             .with_atlas_rows(16)
             .load(gl, res)?;
 
-        // generate a chunk
-        let chunk = Chunk::new((0, 0, 0).into(), gl, &texture)?;
+        let registry = Arc::new(BlockRegistry::from_res(res, "blocks.ron")?);
+        let generator = HeightmapGenerator::new(seed, &registry)?;
+        let storage = Arc::new(WorldStorage::open(save_directory, Arc::clone(&registry))?);
+
+        // leave one core for the render thread
+        let worker_count = thread::available_parallelism()
+            .map(|count| count.get().saturating_sub(1).max(1))
+            .unwrap_or(1);
+        let workers = ChunkWorkers::new(
+            worker_count,
+            Arc::new(generator),
+            Arc::clone(&storage),
+            Arc::clone(&registry),
+            texture.atlas(),
+        );
 
         Result::Ok(World {
-            chunk,
+            chunks: HashMap::new(),
+            registry,
+            storage,
+            pending: HashSet::new(),
+            saving: HashMap::new(),
+            unsaved: HashMap::new(),
+            failed: HashSet::new(),
+            errors: VecDeque::new(),
+            workers,
+            mesh_revision: 0,
+            streamer: ChunkStreamer::new(VIEW_RADIUS, VERTICAL_RADIUS, LOAD_BUDGET),
+            clock: WorldClock::new(START_TIME),
+            mesh_options: MeshOptions::default(),
             program,
             texture,
             view_location,
             projection_location,
-//            camera_pos_location,
             tex_face_location,
+            tile_size_location,
+            sky_light_location,
+            camera_pos_location,
+            fog_color_location,
+            fog_range_location,
         })
     }
 
+    pub fn registry(&self) -> &Arc<BlockRegistry> {
+        &self.registry
+    }
+
+    pub fn mesh_options(&self) -> MeshOptions {
+        self.mesh_options
+    }
+
+    /// Change how chunk meshes are built, rebuilding all loaded meshes.
+    pub fn set_mesh_options(&mut self, options: MeshOptions) {
+        self.mesh_options = options;
+
+        for chunk in self.chunks.values_mut() {
+            chunk.invalidate_mesh();
+        }
+    }
+
+    /// Queue loading or generation of the chunk at the given chunk coordinates
+    /// on the worker threads. The chunk is inserted into the world by a later
+    /// `update` once it is ready.
+    pub fn request_chunk(&mut self, position: ChunkPos) {
+        if let Some(chunk) = self.unsaved.remove(&position) {
+            self.add_chunk(chunk);
+            return;
+        }
+
+        if self.chunks.contains_key(&position)
+            || self.saving.contains_key(&position)
+            || self.failed.contains(&position)
+            || !self.pending.insert(position) {
+            return;
+        }
+
+        self.workers.submit(Job::Generate { position });
+    }
+
+    /// Insert a chunk at its own position, returning the chunk it replaced.
+    pub fn insert_chunk(&mut self, chunk: Chunk) -> Option<Chunk> {
+        self.chunks.insert(chunk.position, chunk)
+    }
+
+    /// Remove the chunk at the given chunk coordinates from the world. The
+    /// chunk is not saved.
+    pub fn remove_chunk(&mut self, position: &ChunkPos) -> Option<Chunk> {
+        self.chunks.remove(position)
+    }
+
+    pub fn get_chunk(&self, position: &ChunkPos) -> Option<&Chunk> {
+        self.chunks.get(position)
+    }
+
+    pub fn get_chunk_mut(&mut self, position: &ChunkPos) -> Option<&mut Chunk> {
+        self.chunks.get_mut(position)
+    }
+
+    pub fn chunks(&self) -> hash_map::Values<'_, ChunkPos, Chunk> {
+        self.chunks.values()
+    }
+
+    pub fn chunks_mut(&mut self) -> hash_map::ValuesMut<'_, ChunkPos, Chunk> {
+        self.chunks.values_mut()
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Approximate number of bytes used by the block and light data of all
+    /// loaded chunks.
+    pub fn memory_usage(&self) -> usize {
+        self.chunks.values().map(Chunk::memory_usage).sum()
+    }
+
+    /// Get the block at a world position, or `None` if the chunk containing it
+    /// is not loaded.
+    pub fn get_block(&self, position: BlockPos) -> Option<Block> {
+        let (chunk_position, local_position) = position.split();
+
+        self.chunks.get(&chunk_position)
+            .map(|chunk| chunk.get_block(local_position))
+    }
+
+    /// Find the first solid block along a ray, within `max_distance` of its
+    /// origin, among the loaded chunks.
+    pub fn raycast(&self, ray: &Ray, max_distance: f32) -> Option<RaycastHit> {
+        super::raycast(self, &self.registry, ray, max_distance)
+    }
+
+    /// Set the block at a world position.
+    ///
+    /// Returns `false` if the chunk containing the position is not loaded. The
+    /// light around the block is updated, and every chunk whose blocks or
+    /// light changed is remeshed, along with any neighbouring chunk sharing a
+    /// face, an edge or a corner with a changed cell.
+    pub fn set_block(&mut self, position: BlockPos, block: Block) -> bool {
+        let (chunk_position, local_position) = position.split();
+
+        match self.chunks.get_mut(&chunk_position) {
+            Some(chunk) => chunk.set_block(local_position, block),
+            None => return false,
+        }
+
+        let mut access = WorldLightAccess::new(&mut self.chunks);
+        access.invalidate(position);
+        light::update_block(&mut access, &self.registry, position);
+        access.finish();
+
+        true
+    }
+
+    /// Set many blocks at once, skipping positions whose chunk is not loaded.
+    /// Returns the number of blocks set.
+    ///
+    /// Unlike `set_block`, the light is not updated after every block: the
+    /// changed chunks and their neighbours are lit again from scratch once
+    /// every block is set, and each of them is remeshed once.
+    pub fn set_blocks<I>(&mut self, blocks: I) -> usize
+    where
+        I: IntoIterator<Item = (BlockPos, Block)>,
+    {
+        let mut placed = 0;
+        let mut changed = HashSet::new();
+        for (position, block) in blocks {
+            let (chunk_position, local_position) = position.split();
+
+            if let Some(chunk) = self.chunks.get_mut(&chunk_position) {
+                chunk.set_block(local_position, block);
+                changed.insert(chunk_position);
+                placed += 1;
+            }
+        }
+
+        // light from the changed chunks can have spread into any neighbour,
+        // where it may no longer have a source
+        let relit: HashSet<ChunkPos> = changed.iter()
+            .flat_map(|&position| surrounding_chunks(position))
+            .filter(|position| self.chunks.contains_key(position))
+            .collect();
+        for position in &relit {
+            let chunk = self.chunks.get_mut(position).unwrap();
+            let mut light_data = ChunkData::new(LightLevel::default());
+            light::light_chunk(&self.registry, *position, chunk.block_data(), &mut light_data);
+            chunk.set_light_data(light_data);
+        }
+
+        // joined from the top down, so that sunlight is taken away from
+        // covered columns before the chunks below them are joined
+        let mut relit: Vec<ChunkPos> = relit.into_iter().collect();
+        relit.sort_by_key(|position| Reverse(position.z));
+
+        let mut access = WorldLightAccess::new(&mut self.chunks);
+        for &position in &relit {
+            access.invalidated.extend(surrounding_chunks(position));
+            light::join_chunk(&mut access, &self.registry, position);
+        }
+        access.finish();
+
+        placed
+    }
+
+    /// Save every chunk with unsaved changes, including unloaded chunks
+    /// which are still being saved or failed to save, and wait for it to
+    /// finish.
+    ///
+    /// Dropping the world only finishes the saves already queued for
+    /// unloaded chunks, so this has to be called before the world is dropped
+    /// to keep changes to loaded chunks.
+    pub fn save(&mut self) -> Result<(), storage::Error> {
+        let modified: Vec<(ChunkPos, _)> = self.chunks.values()
+            .chain(self.saving.values())
+            .chain(self.unsaved.values())
+            .filter(|chunk| chunk.is_modified())
+            .map(|chunk| (chunk.position, chunk.block_data()))
+            .collect();
+        self.storage.save_chunks(&modified)?;
+
+        for chunk in self.chunks.values_mut() {
+            chunk.mark_saved();
+        }
+        self.unsaved.clear();
+
+        Ok(())
+    }
+
+    /// Stream chunks in and out around the viewer, then rebuild any
+    /// invalidated chunk meshes. Chunks with unsaved changes are saved as
+    /// they are unloaded.
+    ///
+    /// Loading, generation, meshing and saving happen on the worker threads;
+    /// this only uploads the finished meshes. Errors from the workers, such
+    /// as a chunk failing to save, are returned one per update after the rest
+    /// of the update is done.
     pub fn update(
         &mut self,
         gl: &gl::Gl,
-    ) {
-        self.chunk.update(gl);
+        viewer: &na::Point3<f32>,
+    ) -> Result<(), failure::Error> {
+        let center = BlockPos::from(viewer).chunk_pos();
+
+        self.receive_chunks(gl, &center);
+
+        // chunks still being saved, or which failed to load, count as loaded
+        // so they do not take up the load budget while they can not be loaded
+        let resident = self.chunks.keys()
+            .chain(self.pending.iter())
+            .chain(self.saving.keys())
+            .chain(self.failed.iter());
+        let plan = self.streamer.plan(center, resident);
+
+        // unloaded chunks are held on to until their changes are saved
+        let modified: Vec<Chunk> = plan.unload.iter()
+            .filter_map(|position| self.remove_chunk(position))
+            .filter(|chunk| chunk.is_modified())
+            .collect();
+        if !modified.is_empty() {
+            self.workers.submit(Job::Save {
+                chunks: modified.iter()
+                    .map(|chunk| (chunk.position, chunk.block_data().clone()))
+                    .collect(),
+            });
+            self.saving.extend(modified.into_iter().map(|chunk| (chunk.position, chunk)));
+        }
+
+        for position in plan.load {
+            if self.pending.len() >= MAX_PENDING_CHUNKS {
+                break;
+            }
+            self.request_chunk(position);
+        }
+
+        self.remesh_chunks();
+
+        match self.errors.pop_front() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    /// Queue a mesh rebuild for every chunk whose mesh has been invalidated.
+    fn remesh_chunks(&mut self) {
+        let invalidated: Vec<ChunkPos> = self.chunks.values()
+            .filter(|chunk| chunk.needs_remesh())
+            .map(|chunk| chunk.position)
+            .collect();
+
+        for position in invalidated {
+            let block_data = self.padded_block_data(&position);
+            let light_data = self.padded_light_data(&position);
+            self.mesh_revision += 1;
+            let revision = self.mesh_revision;
+            self.chunks.get_mut(&position).unwrap().start_remesh(revision);
+
+            self.workers.submit(Job::Mesh {
+                position,
+                revision,
+                block_data,
+                light_data,
+                options: self.mesh_options,
+            });
+        }
+    }
+
+    /// Copy the blocks of a chunk along with the border of its neighbours.
+    /// Neighbours which are not loaded are filled with air.
+    fn padded_block_data(&self, position: &ChunkPos) -> PaddedChunkData<Block> {
+        PaddedChunkData::from_chunks(block::AIR, |offset| {
+            let neighbor_position = *position + Position::new(offset.x, offset.y, offset.z);
+
+            self.chunks.get(&neighbor_position).map(Chunk::block_data)
+        })
+    }
+
+    /// Copy the light of a chunk along with the border of its neighbours.
+    /// Neighbours which are not loaded are filled with sunlight.
+    fn padded_light_data(&self, position: &ChunkPos) -> PaddedChunkData<LightLevel> {
+        PaddedChunkData::from_chunks(LightLevel::new(SUNLIGHT, LightColor::default()), |offset| {
+            let neighbor_position = *position + Position::new(offset.x, offset.y, offset.z);
+
+            self.chunks.get(&neighbor_position).map(Chunk::light_data)
+        })
+    }
+
+    /// Apply the results of finished worker jobs.
+    fn receive_chunks(&mut self, gl: &gl::Gl, center: &ChunkPos) {
+        while let Some(result) = self.workers.try_recv() {
+            match result {
+                JobResult::Generated { position, block_data, light_data, load_error } => {
+                    self.pending.remove(&position);
+                    self.errors.extend(load_error);
+
+                    // the viewer may have moved away while it was generated
+                    if !self.streamer.should_keep(center, &position) {
+                        continue;
+                    }
+
+                    self.add_chunk(Chunk::new(position, block_data, light_data));
+                }
+                JobResult::LoadFailed { position, error } => {
+                    self.pending.remove(&position);
+                    self.failed.insert(position);
+                    self.errors.push_back(error);
+                }
+                JobResult::Meshed { position, revision, mesh } => {
+                    if let Some(chunk) = self.chunks.get_mut(&position) {
+                        chunk.finish_remesh(gl, revision, &mesh);
+                    }
+                }
+                JobResult::Saved { positions, result } => {
+                    let chunks: Vec<Chunk> = positions.iter()
+                        .filter_map(|position| self.saving.remove(position))
+                        .collect();
+                    if let Err(error) = result {
+                        self.unsaved.extend(chunks.into_iter().map(|chunk| (chunk.position, chunk)));
+                        self.errors.push_back(error.into());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Insert a newly loaded chunk, joining its light with its neighbours.
+    fn add_chunk(&mut self, chunk: Chunk) {
+        let position = chunk.position;
+        self.insert_chunk(chunk);
+
+        // faces bordering the new chunk can now be culled and shaded
+        for neighbor_position in surrounding_chunks(position) {
+            if let Some(neighbor) = self.chunks.get_mut(&neighbor_position) {
+                neighbor.invalidate_mesh();
+            }
+        }
+
+        // light can now spread to and from the new chunk
+        let mut access = WorldLightAccess::new(&mut self.chunks);
+        light::join_chunk(&mut access, &self.registry, position);
+        access.finish();
     }
 
     pub fn draw(
@@ -61,7 +487,142 @@ impl World {
         self.program.set_uniform_matrix4fv(self.view_location, view_matrix);
         self.program.set_uniform_matrix4fv(self.projection_location, projection_matrix);
         self.program.set_uniform_1i(self.tex_face_location, 0);
+        self.program.set_uniform_1f(self.tile_size_location, self.texture.atlas().tile_size());
+        self.program.set_uniform_1f(self.sky_light_location, self.clock.sky_light());
+
+        // fade into the sky before reaching the edge of the loaded chunks
+        let view_distance = (self.streamer.view_radius * CHUNK_SIZE) as f32;
+        self.program.set_uniform_3f(self.camera_pos_location, camera_pos);
+        self.program.set_uniform_3f(self.fog_color_location, &self.clock.sky_color());
+        self.program.set_uniform_2f(
+            self.fog_range_location,
+            &na::Vector2::new(view_distance * FOG_START, view_distance),
+        );
+
+        for chunk in self.chunks.values() {
+            chunk.draw(gl);
+        }
+    }
+}
+
+impl BlockAccess for World {
+    fn get_block(&self, position: BlockPos) -> Option<Block> {
+        World::get_block(self, position)
+    }
+
+    fn set_block(&mut self, position: BlockPos, block: Block) -> bool {
+        World::set_block(self, position, block)
+    }
+
+    fn set_blocks<I>(&mut self, blocks: I) -> usize
+    where
+        I: IntoIterator<Item = (BlockPos, Block)>,
+    {
+        World::set_blocks(self, blocks)
+    }
+}
+
+/// A chunk and every chunk sharing a face, an edge or a corner with it.
+fn surrounding_chunks(position: ChunkPos) -> Vec<ChunkPos> {
+    let mut chunks = Vec::with_capacity(27);
+    for x in -1..=1 {
+        for y in -1..=1 {
+            for z in -1..=1 {
+                chunks.push(position + Position::new(x, y, z));
+            }
+        }
+    }
+
+    chunks
+}
+
+/// Offsets of the neighbouring chunks whose meshes can depend on the block at
+/// the given local position: those sharing a face, an edge or a corner with
+/// it, since the corners of faces are shaded by the blocks diagonally around
+/// them.
+fn border_neighbors(local_position: LocalPos) -> Vec<Position> {
+    let position = Position::from(local_position);
+
+    // the offsets towards the border of the chunk along each axis
+    let mut axis_offsets: [Vec<i64>; 3] = [vec![0], vec![0], vec![0]];
+    for (axis, offsets) in axis_offsets.iter_mut().enumerate() {
+        if position.get(axis) == 0 {
+            offsets.push(-1);
+        } else if position.get(axis) == CHUNK_SIZE - 1 {
+            offsets.push(1);
+        }
+    }
+
+    let mut neighbors = Vec::new();
+    for &x in &axis_offsets[0] {
+        for &y in &axis_offsets[1] {
+            for &z in &axis_offsets[2] {
+                if (x, y, z) != (0, 0, 0) {
+                    neighbors.push(Position::new(x, y, z));
+                }
+            }
+        }
+    }
+
+    neighbors
+}
+
+/// Light access to every loaded chunk, keeping track of which meshes have to
+/// be rebuilt for the light to be seen.
+struct WorldLightAccess<'a> {
+    chunks: &'a mut HashMap<ChunkPos, Chunk>,
+    invalidated: HashSet<ChunkPos>,
+}
+
+impl<'a> WorldLightAccess<'a> {
+    fn new(chunks: &'a mut HashMap<ChunkPos, Chunk>) -> WorldLightAccess<'a> {
+        WorldLightAccess {
+            chunks,
+            invalidated: HashSet::new(),
+        }
+    }
+
+    /// Remesh the chunk containing a cell, and any neighbour sharing a face,
+    /// an edge or a corner with it.
+    fn invalidate(&mut self, position: BlockPos) {
+        let (chunk_position, local_position) = position.split();
+
+        self.invalidated.insert(chunk_position);
+        for neighbor_offset in border_neighbors(local_position) {
+            self.invalidated.insert(chunk_position + neighbor_offset);
+        }
+    }
+
+    fn finish(self) {
+        for position in &self.invalidated {
+            if let Some(chunk) = self.chunks.get_mut(position) {
+                chunk.invalidate_mesh();
+            }
+        }
+    }
+}
+
+impl LightAccess for WorldLightAccess<'_> {
+    fn block(&self, position: BlockPos) -> Option<Block> {
+        let (chunk_position, local_position) = position.split();
+
+        self.chunks.get(&chunk_position)
+            .map(|chunk| chunk.get_block(local_position))
+    }
+
+    fn light(&self, position: BlockPos) -> Option<LightLevel> {
+        let (chunk_position, local_position) = position.split();
+
+        self.chunks.get(&chunk_position)
+            .map(|chunk| chunk.get_light(local_position))
+    }
+
+    fn set_light(&mut self, position: BlockPos, light: LightLevel) {
+        let (chunk_position, local_position) = position.split();
 
-        self.chunk.draw(gl);
+        if let Some(chunk) = self.chunks.get_mut(&chunk_position) {
+            chunk.set_light(local_position, light);
+            self.invalidate(position);
+        }
     }
 }